@@ -10,7 +10,8 @@ use clap::Parser;
 use hokkaido::gojo::{
     cli::Cli,
     parser::{self, Parser as _, Statement},
-    Color, Gojo,
+    set::GojoSet,
+    Color,
 };
 
 fn read_from_stdin(buf: &mut String) -> Result<()> {
@@ -34,14 +35,23 @@ fn convert_color_to_str(c: Color) -> String {
     }
 }
 
-fn process_statements(stms: Vec<Statement>) -> Result<String> {
-    let mut gojo: Gojo<i32, i32> = Gojo::default();
+// How IMP statements print a version
+#[derive(Debug, Copy, Clone)]
+enum PrintMode {
+    Nodes,
+    Dot,
+    DotFat,
+}
+
+fn process_statements(stms: Vec<Statement>, mode: PrintMode) -> Result<String> {
+    // Every statement makes a version, inserting a key again included
+    let mut gojo: GojoSet<i32> = GojoSet::new(stms.len() + 1);
     let mut str_list: Vec<String> = Vec::new();
 
     for stm in stms {
         match stm {
             parser::Statement::Insert(value) => {
-                gojo.insert(value, value);
+                gojo.college_insert(value);
             }
             parser::Statement::Remove(value) => {
                 gojo.college_remove(&value);
@@ -55,11 +65,25 @@ fn process_statements(stms: Vec<Statement>) -> Result<String> {
                     version
                 };
 
-                match gojo.successor_by_key(&value, real_version) {
+                match gojo.successor(&value, real_version) {
                     Some(succ) => str_list.push(format!("{succ}")),
                     None => str_list.push(INFINITE_KEYWORD.to_string()),
                 }
             }
+            parser::Statement::Predecessor { value, version } => {
+                str_list.push(format!("ANT {value} {version}"));
+
+                let real_version = if version > gojo.latest_version() {
+                    gojo.latest_version()
+                } else {
+                    version
+                };
+
+                match gojo.predecessor(&value, real_version) {
+                    Some(pred) => str_list.push(format!("{pred}")),
+                    None => str_list.push(format!("-{INFINITE_KEYWORD}")),
+                }
+            }
             parser::Statement::Print(version) => {
                 str_list.push(format!("IMP {version}"));
 
@@ -69,13 +93,25 @@ fn process_statements(stms: Vec<Statement>) -> Result<String> {
                     version
                 };
 
+                match mode {
+                    PrintMode::Nodes => {}
+                    PrintMode::Dot => {
+                        str_list.push(gojo.to_dot(real_version)?);
+                        continue;
+                    }
+                    PrintMode::DotFat => {
+                        str_list.push(gojo.to_dot_fat());
+                        continue;
+                    }
+                }
+
                 let mut list: Vec<String> =
                     Vec::with_capacity(gojo.len(real_version).expect("Should not come here"));
 
                 for info in gojo.node_info_iter(real_version)? {
                     list.push(format!(
                         "{},{},{}",
-                        info.value,
+                        info.key,
                         info.depth,
                         convert_color_to_str(info.color)
                     ));
@@ -113,7 +149,12 @@ fn main() -> Result<()> {
     let parser = parser::ParserVagaba::default();
     let stms = parser.parse_lines(&buf)?;
 
-    let mut output_string = process_statements(stms)?;
+    let mode = match (cli.dot, cli.fat) {
+        (false, _) => PrintMode::Nodes,
+        (true, false) => PrintMode::Dot,
+        (true, true) => PrintMode::DotFat,
+    };
+    let mut output_string = process_statements(stms, mode)?;
 
     if cli.new_line {
         output_string.push('\n');
@@ -0,0 +1,82 @@
+use std::{hint::black_box, time::Instant};
+
+use anyhow::Result;
+use clap::Parser;
+use hokkaido::gojo::{DuplicatePolicy, Gojo};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Compares Gojo fat node capacities on the same random workload
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Updates applied to every tree, each one makes a version
+    #[arg(long, default_value_t = 5_000)]
+    ops: usize,
+
+    /// Lookups timed on random versions
+    #[arg(long, default_value_t = 20_000)]
+    queries: usize,
+
+    /// Updates derive from one of the latest `branch` versions
+    #[arg(long, default_value_t = 1)]
+    branch: usize,
+
+    /// Seed of the workload, the same for every capacity
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+// Builds a tree with `MODS` mods per fat node and prints its row
+fn run<const MODS: usize>(cli: &Cli) -> Result<()> {
+    let keys = (cli.ops / 2).max(1) as i64;
+    let mut gojo: Gojo<i64, i64, MODS> = Gojo::with_mods(cli.ops + 1, DuplicatePolicy::Replace);
+    let mut rng = StdRng::seed_from_u64(cli.seed);
+
+    let start = Instant::now();
+    for _ in 0..cli.ops {
+        let latest = gojo.latest_version();
+        let from = rng.gen_range(latest.saturating_sub(cli.branch.max(1) - 1)..=latest);
+        let key = rng.gen_range(0..keys);
+        if gojo.remove_at(from, &key)?.is_none() {
+            gojo.insert_at(from, key, key)?;
+        }
+    }
+    let build = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..cli.queries {
+        let version = rng.gen_range(0..=gojo.latest_version());
+        black_box(gojo.get(&rng.gen_range(0..keys), version));
+    }
+    let query = start.elapsed();
+
+    let stats = gojo.arena_stats();
+    println!(
+        "{MODS:>4} {:>10} {:>10} {:>12} {:>10.1} {:>10.1}",
+        stats.nodes,
+        stats.copies,
+        stats.bytes,
+        build.as_nanos() as f64 / cli.ops.max(1) as f64,
+        query.as_nanos() as f64 / cli.queries.max(1) as f64,
+    );
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    println!(
+        "{:>4} {:>10} {:>10} {:>12} {:>10} {:>10}",
+        "mods", "nodes", "copies", "bytes", "update ns", "query ns"
+    );
+    run::<3>(&cli)?;
+    run::<4>(&cli)?;
+    run::<6>(&cli)?;
+    run::<8>(&cli)?;
+    run::<12>(&cli)?;
+    run::<16>(&cli)?;
+    run::<32>(&cli)?;
+
+    Ok(())
+}
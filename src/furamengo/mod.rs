@@ -57,7 +57,7 @@ impl<K: Ord, V> Ord for NodePtr<K, V> {
 
 impl<K: Ord, V> PartialOrd for NodePtr<K, V> {
     fn partial_cmp(&self, other: &NodePtr<K, V>) -> Option<Ordering> {
-        unsafe { Some((*self.0).key.cmp(&(*other.0).key)) }
+        Some(self.cmp(other))
     }
 
     fn lt(&self, other: &Self) -> bool {
@@ -351,7 +351,7 @@ where
         }
 
         self.iter()
-            .all(|(key, value)| other.get(key).map_or(false, |v| *value == *v))
+            .all(|(key, value)| other.get(key).is_some_and(|v| *value == *v))
     }
 }
 
@@ -362,7 +362,7 @@ where
 {
 }
 
-impl<'a, K, V> Index<&'a K> for Furamengo<K, V>
+impl<K, V> Index<&K> for Furamengo<K, V>
 where
     K: Ord,
 {
@@ -1124,24 +1124,24 @@ impl<K: Ord, V> Furamengo<K, V> {
     }
 
     /// Return the keys iter
-    pub fn keys(&self) -> Keys<K, V> {
+    pub fn keys(&self) -> Keys<'_, K, V> {
         Keys { inner: self.iter() }
     }
 
     /// Return the value iter
-    pub fn values(&self) -> Values<K, V> {
+    pub fn values(&self) -> Values<'_, K, V> {
         Values { inner: self.iter() }
     }
 
     /// Return the value iter mut
-    pub fn values_mut(&mut self) -> ValuesMut<K, V> {
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
         ValuesMut {
             inner: self.iter_mut(),
         }
     }
 
     /// Return the key and value iter
-    pub fn iter(&self) -> Iter<K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
             head: self.first_child(),
             tail: self.last_child(),
@@ -1151,7 +1151,7 @@ impl<K: Ord, V> Furamengo<K, V> {
     }
 
     /// Return the key and mut value iter
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
         IterMut {
             head: self.first_child(),
             tail: self.last_child(),
@@ -0,0 +1,302 @@
+use anyhow::Result;
+
+use super::{DuplicatePolicy, Gojo, Monoid};
+
+// A change recorded by a `GojoTx`, applied once the batch succeeds
+enum Change<K, V> {
+    Insert(K, V),
+    Remove(K),
+}
+
+/// Changes collected by `Gojo::batch`, applied in order and together in a
+/// single version once the batch succeeds.
+pub struct GojoTx<'a, K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()> {
+    gojo: &'a Gojo<K, V, MODS, M>,
+    from: usize,
+    changes: Vec<Change<K, V>>,
+}
+
+impl<'a, K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> GojoTx<'a, K, V, MODS, M> {
+    /// Version the batch derives from.
+    pub fn from_version(&self) -> usize {
+        self.from
+    }
+
+    /// Value of `k` in the version the batch derives from, the changes of the
+    /// batch are not seen.
+    pub fn get(&self, k: &K) -> Option<&'a V> {
+        self.gojo.get(k, self.from)
+    }
+
+    /// Same as `get`, the changes of the batch are not seen.
+    pub fn contains_key(&self, k: &K) -> bool {
+        self.gojo.contains_key(k, self.from)
+    }
+
+    /// Inserts `k` when the batch is applied, a key already there by then is
+    /// handled as the duplicate policy says.
+    pub fn insert(&mut self, k: K, v: V) {
+        self.changes.push(Change::Insert(k, v));
+    }
+
+    /// Removes `k` when the batch is applied, if it is there by then.
+    pub fn remove(&mut self, k: &K) {
+        self.changes.push(Change::Remove(k.clone()));
+    }
+
+    /// Changes recorded so far.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M> {
+    /// Runs `f` and applies the changes it records in a single new version
+    /// derived from the latest one, even if they change nothing. Returns that
+    /// version. When `f` fails nothing is applied and no version is created.
+    pub fn batch<F>(&mut self, f: F) -> Result<usize>
+    where
+        F: FnOnce(&mut GojoTx<'_, K, V, MODS, M>) -> Result<()>,
+    {
+        self.batch_at(self.curr_version, f)
+    }
+
+    /// Same as `batch`, with the new version derived from `version`.
+    pub fn batch_at<F>(&mut self, version: usize, f: F) -> Result<usize>
+    where
+        F: FnOnce(&mut GojoTx<'_, K, V, MODS, M>) -> Result<()>,
+    {
+        self.root_entry(version)?;
+        let mut tx = GojoTx {
+            gojo: self,
+            from: version,
+            changes: Vec::new(),
+        };
+        f(&mut tx)?;
+
+        let changes = tx.changes;
+        self.branch(version);
+        for change in changes {
+            self.apply(change);
+        }
+        self.publish(version);
+        Ok(self.curr_version)
+    }
+
+    // Applies `change` to the version in progress, every mod it makes is
+    // stamped with that version
+    fn apply(&mut self, change: Change<K, V>) {
+        let version = self.curr_version;
+        match change {
+            Change::Insert(k, v) => {
+                let node = self.find_below(self.root, &k, version);
+                if node.is_null() {
+                    self.link_node(k, v);
+                    return;
+                }
+                match self.policy {
+                    DuplicatePolicy::Reject => {}
+                    DuplicatePolicy::Replace => {
+                        self.arena.set_value(node, v, version);
+                        self.update_aggs_up(node);
+                    }
+                    DuplicatePolicy::Multi => {
+                        let count = self.arena.count(node, version) + 1;
+                        self.arena.set_count(node, count, version);
+                    }
+                }
+            }
+            Change::Remove(k) => {
+                let node = self.find_below(self.root, &k, version);
+                if node.is_null() {
+                    return;
+                }
+                match self.arena.count(node, version) {
+                    1 => {
+                        self.unlink_node(node);
+                    }
+                    count => self.arena.set_count(node, count - 1, version),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::gojo::{DuplicatePolicy, Gojo, GojoError};
+    use anyhow::Result;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_batch_makes_a_single_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        gojo.insert(0, 0);
+
+        // Act
+        let version = gojo.batch(|tx| {
+            for k in 1..=500 {
+                tx.insert(k, k);
+            }
+            tx.remove(&0);
+            Ok(())
+        })?;
+
+        // Assert
+        assert_eq!(2, version);
+        assert_eq!(2, gojo.latest_version());
+        assert_eq!(Some(500), gojo.len(2));
+        assert_eq!(Some(1), gojo.len(1));
+        assert_eq!(Some(1), gojo.parent_version(2));
+        assert!(gojo.validate_all().is_ok(), "{:?}", gojo.validate_all());
+        let expected: Vec<i32> = (1..=500).collect();
+        let actual: Vec<i32> = gojo.iter(2)?.map(|(k, _)| *k).collect();
+        assert_eq!(expected, actual);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_changes_each_field_once() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+
+        // Act
+        gojo.batch(|tx| {
+            for k in 0..100 {
+                tx.insert(k, k);
+            }
+            Ok(())
+        })?;
+
+        // Assert
+        let stats = gojo.arena_stats();
+        assert_eq!(100, stats.nodes);
+        assert_eq!(0, stats.copies);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_applies_changes_in_order() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, &str> = Gojo::with_policy(10, DuplicatePolicy::Replace);
+        gojo.insert(1, "one");
+
+        // Act
+        gojo.batch(|tx| {
+            assert_eq!(Some(&"one"), tx.get(&1));
+            tx.remove(&1);
+            tx.insert(1, "uno");
+            tx.insert(2, "two");
+            tx.insert(2, "dos");
+            tx.insert(3, "three");
+            tx.remove(&3);
+            assert!(!tx.contains_key(&2));
+            assert_eq!(6, tx.len());
+            Ok(())
+        })?;
+
+        // Assert
+        assert_eq!(
+            vec![(&1, &"uno"), (&2, &"dos")],
+            gojo.iter(2)?.collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_batch_rolls_back() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        gojo.insert(1, 1);
+        let nodes = gojo.arena_stats().nodes;
+
+        // Act
+        let res = gojo.batch(|tx| {
+            tx.insert(2, 2);
+            tx.remove(&1);
+            anyhow::bail!("nope")
+        });
+
+        // Assert
+        assert_eq!("nope", res.unwrap_err().to_string());
+        assert_eq!(1, gojo.latest_version());
+        assert_eq!(nodes, gojo.arena_stats().nodes);
+        assert_eq!(vec![(&1, &1)], gojo.iter(1)?.collect::<Vec<_>>());
+        gojo.insert(3, 3);
+        assert_eq!(Some(2), gojo.len(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_at_unknown_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+
+        // Act
+        let err = gojo.batch_at(1, |_| Ok(())).unwrap_err();
+
+        // Assert
+        assert_eq!(
+            "the version `1` is not available",
+            err.downcast::<GojoError>()?.to_string()
+        );
+        assert_eq!(0, gojo.latest_version());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_batches_match_naive_snapshots() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(22);
+        let mut gojo: Gojo<i32, i32> = Gojo::with_policy(10, DuplicatePolicy::Replace);
+        let mut snapshots = vec![BTreeMap::new()];
+
+        // Act
+        for _ in 0..150 {
+            let from = rng.gen_range(snapshots.len().saturating_sub(10)..snapshots.len());
+            let mut snapshot: BTreeMap<i32, i32> = snapshots[from].clone();
+            let changes: Vec<(i32, Option<i32>)> = (0..rng.gen_range(0..20))
+                .map(|_| (rng.gen_range(0..60), rng.gen_bool(0.6).then(|| rng.gen())))
+                .collect();
+            gojo.batch_at(from, |tx| {
+                for (k, v) in &changes {
+                    match v {
+                        Some(v) => tx.insert(*k, *v),
+                        None => tx.remove(k),
+                    }
+                }
+                Ok(())
+            })?;
+            for (k, v) in changes {
+                match v {
+                    Some(v) => snapshot.insert(k, v),
+                    None => snapshot.remove(&k),
+                };
+            }
+            snapshots.push(snapshot);
+        }
+
+        // Assert
+        assert!(gojo.validate_all().is_ok(), "{:?}", gojo.validate_all());
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            let actual: Vec<(i32, i32)> = gojo.iter(version)?.map(|(k, v)| (*k, *v)).collect();
+            let expected: Vec<(i32, i32)> = snapshot.iter().map(|(k, v)| (*k, *v)).collect();
+            assert_eq!(expected, actual, "for version {version}");
+        }
+
+        Ok(())
+    }
+}
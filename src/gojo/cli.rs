@@ -16,4 +16,12 @@ pub struct Cli {
     /// Trailing newline
     #[arg(short, long)]
     pub new_line: bool,
+
+    /// Prints the version of IMP statements as a Graphviz DOT graph
+    #[arg(long)]
+    pub dot: bool,
+
+    /// With --dot, draws every fat node of the tree instead of one version
+    #[arg(long, requires = "dot")]
+    pub fat: bool,
 }
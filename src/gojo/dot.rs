@@ -0,0 +1,284 @@
+use anyhow::Result;
+use std::fmt::Debug;
+
+use super::{Color, Gojo, ModData, Monoid, NodePtr};
+
+impl<K, V, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M>
+where
+    K: Ord + Clone + Debug,
+    V: Clone + Debug,
+{
+    /// Graphviz DOT of the tree seen from `version`, every node with its key,
+    /// value, depth and color.
+    pub fn to_dot(&self, version: usize) -> Result<String> {
+        self.dot(version, |k, v| format!("{k:?}: {v:?}"))
+    }
+
+    /// Graphviz DOT of every fat node in the arena: its fields, its table of
+    /// mods and the copy made once the table was full, plus the root of every
+    /// version in history.
+    pub fn to_dot_fat(&self) -> String {
+        self.dot_fat(|k, v| format!("{k:?}: {v:?}"))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + Debug, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M> {
+    // `to_dot` with every node labeled by `label`
+    pub(super) fn dot(&self, version: usize, label: impl Fn(&K, &V) -> String) -> Result<String> {
+        let (root, _, _) = self.root_entry(version)?;
+        let mut lines = vec![
+            format!("digraph \"version {version}\" {{"),
+            "    node [style=filled, fontcolor=white, fontname=monospace];".to_string(),
+        ];
+        let root = self.arena.get_last_copy(root, version);
+        if !root.is_null() {
+            self.dot_subtree(root, version, 0, &label, &mut lines);
+        }
+        lines.push("}".to_string());
+
+        Ok(lines.join("\n"))
+    }
+
+    fn dot_subtree(
+        &self,
+        node: NodePtr,
+        version: usize,
+        depth: usize,
+        label: &impl Fn(&K, &V) -> String,
+        lines: &mut Vec<String>,
+    ) {
+        let arena = &self.arena;
+        let text = label(arena.key(node), arena.value_at(node, version));
+        lines.push(format!(
+            "    {} [label=\"{}\\ndepth {depth}\", fillcolor={}];",
+            dot_ptr(node),
+            escape_quoted(&text),
+            dot_color(arena.get_color(node, version)),
+        ));
+
+        // Missing children are drawn as points, so left and right stay apart
+        for (side, child) in [
+            ("l", arena.left(node, version)),
+            ("r", arena.right(node, version)),
+        ] {
+            match child.is_null() {
+                true => {
+                    let nil = format!("nil{}{side}", node.index());
+                    lines.push(format!("    {nil} [shape=point, fillcolor=black];"));
+                    lines.push(format!("    {} -> {nil};", dot_ptr(node)));
+                }
+                false => {
+                    lines.push(format!("    {} -> {};", dot_ptr(node), dot_ptr(child)));
+                    self.dot_subtree(child, version, depth + 1, label, lines);
+                }
+            }
+        }
+    }
+
+    // `to_dot_fat` with every node labeled by `label`
+    pub(super) fn dot_fat(&self, label: impl Fn(&K, &V) -> String) -> String {
+        let mut lines = vec![
+            "digraph gojo {".to_string(),
+            "    node [shape=plaintext, fontname=monospace];".to_string(),
+        ];
+        for (index, node) in self.arena.nodes.iter().enumerate() {
+            let ptr = NodePtr(index as u32);
+            let mut rows = vec![
+                format!(
+                    "<tr><td colspan=\"2\" bgcolor=\"{}\"><font color=\"white\">{} {}</font></td></tr>",
+                    dot_color(node.color),
+                    dot_ptr(ptr),
+                    escape_html(&label(&node.key, &node.value)),
+                ),
+                dot_row("from", &format!("v{}", node.version)),
+            ];
+            if let Some(until) = node.until {
+                rows.push(dot_row("until", &format!("v{until}")));
+            }
+            rows.extend([
+                dot_row("left", &dot_ptr(node.left)),
+                dot_row("right", &dot_ptr(node.right)),
+                dot_row("parent", &dot_ptr(node.parent)),
+                dot_row("size", &node.size.to_string()),
+                dot_row("count", &node.count.to_string()),
+                dot_row("stamp", &node.stamp.to_string()),
+                format!(
+                    "<tr><td colspan=\"2\">mods {}/{MODS}</td></tr>",
+                    node.mods.len()
+                ),
+            ]);
+            rows.extend(
+                node.mods
+                    .iter()
+                    .map(|m| dot_row(&format!("v{}", m.version), &escape_html(&dot_mod(&m.data)))),
+            );
+            lines.push(format!(
+                "    {} [label=<<table border=\"0\" cellborder=\"1\" cellspacing=\"0\">{}</table>>];",
+                dot_ptr(ptr),
+                rows.join("")
+            ));
+            if !node.next_copy.is_null() {
+                lines.push(format!(
+                    "    {} -> {} [style=dashed, label=\"copy\"];",
+                    dot_ptr(ptr),
+                    dot_ptr(node.next_copy)
+                ));
+            }
+        }
+
+        for (offset, (root, _, _)) in self.roots.iter().enumerate() {
+            let version = self.first_version + offset;
+            lines.push(format!("    v{version} [shape=box];"));
+            if !root.is_null() {
+                let root = self.arena.get_last_copy(*root, version);
+                lines.push(format!("    v{version} -> {};", dot_ptr(root)));
+            }
+        }
+        lines.push("}".to_string());
+
+        lines.join("\n")
+    }
+}
+
+fn dot_ptr(ptr: NodePtr) -> String {
+    match ptr.is_null() {
+        true => "nil".to_string(),
+        false => format!("n{}", ptr.index()),
+    }
+}
+
+fn dot_color(color: Color) -> &'static str {
+    match color {
+        Color::Red => "red",
+        Color::Black => "black",
+    }
+}
+
+fn dot_row(name: &str, value: &str) -> String {
+    format!("<tr><td>{name}</td><td>{value}</td></tr>")
+}
+
+fn dot_mod<V: Clone + Debug, S: Clone>(data: &ModData<V, S>) -> String {
+    match data {
+        ModData::Parent(p) => format!("parent {}", dot_ptr(*p)),
+        ModData::Left(l) => format!("left {}", dot_ptr(*l)),
+        ModData::Right(r) => format!("right {}", dot_ptr(*r)),
+        ModData::Col(c) => format!("color {}", dot_color(*c)),
+        ModData::Size(n) => format!("size {n}"),
+        ModData::Count(n) => format!("count {n}"),
+        ModData::Stamp(n) => format!("stamp {n}"),
+        ModData::Value(v) => format!("value {v:?}"),
+        ModData::Agg(_) => "aggregate".to_string(),
+    }
+}
+
+fn escape_quoted(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod dot_tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::gojo::{set::GojoSet, Gojo, GojoError};
+    use anyhow::Result;
+
+    #[test]
+    fn test_to_dot_draws_a_version() -> Result<()> {
+        // Arrange
+        let mut gojo = Gojo::default();
+        for (k, v) in [(2, "two"), (1, "one"), (3, "three")] {
+            gojo.insert(k, v);
+        }
+        gojo.remove(&1);
+
+        // Act
+        let dot = gojo.to_dot(4)?;
+
+        // Assert
+        let expected = [
+            "digraph \"version 4\" {",
+            "    node [style=filled, fontcolor=white, fontname=monospace];",
+            "    n3 [label=\"2: \\\"two\\\"\\ndepth 0\", fillcolor=black];",
+            "    nil3l [shape=point, fillcolor=black];",
+            "    n3 -> nil3l;",
+            "    n3 -> n2;",
+            "    n2 [label=\"3: \\\"three\\\"\\ndepth 1\", fillcolor=red];",
+            "    nil2l [shape=point, fillcolor=black];",
+            "    n2 -> nil2l;",
+            "    nil2r [shape=point, fillcolor=black];",
+            "    n2 -> nil2r;",
+            "}",
+        ];
+        assert_eq!(expected.join("\n"), dot);
+        assert_eq!(
+            "digraph \"version 0\" {\n    node [style=filled, fontcolor=white, fontname=monospace];\n}",
+            gojo.to_dot(0)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_dot_of_unknown_version() -> Result<()> {
+        // Arrange
+        let gojo: Gojo<i32, i32> = Gojo::default();
+
+        // Act
+        let err = gojo.to_dot(1).unwrap_err().downcast::<GojoError>()?;
+
+        // Assert
+        assert_eq!("the version `1` is not available", err.to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_dot_fat_draws_mods_copies_and_roots() {
+        // Arrange
+        let mut gojo: Gojo<i32, i32, 2> = Gojo::with_mods(8, Default::default());
+        for k in [1, 2, 3] {
+            gojo.insert(k, k * 10);
+        }
+
+        // Act
+        let dot = gojo.to_dot_fat();
+
+        // Assert
+        let stats = gojo.arena_stats();
+        let nodes = dot.lines().filter(|line| line.contains("[label=<")).count();
+        assert_eq!(stats.nodes, nodes);
+        assert_eq!(stats.copies, dot.matches("label=\"copy\"").count());
+        assert!(stats.copies > 0);
+        assert!(dot.contains("<font color=\"white\">n0 1: 10</font>"));
+        assert!(dot.contains("<td colspan=\"2\">mods 2/2</td>"));
+        for version in 0..=3 {
+            assert!(dot.contains(&format!("    v{version} [shape=box];")));
+        }
+        assert!(!dot.contains("    v0 ->"));
+    }
+
+    #[test]
+    fn test_set_dot_labels_keys_only() -> Result<()> {
+        // Arrange
+        let mut set: GojoSet<&str> = GojoSet::default();
+        set.insert("a<b");
+
+        // Act
+        let dot = set.to_dot(1)?;
+        let fat = set.to_dot_fat();
+
+        // Assert
+        assert!(dot.contains("    n0 [label=\"\\\"a<b\\\"\\ndepth 0\", fillcolor=black];"));
+        assert!(fat.contains("<font color=\"white\">n0 &quot;a&lt;b&quot;</font>"));
+
+        Ok(())
+    }
+}
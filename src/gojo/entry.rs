@@ -0,0 +1,305 @@
+use std::cmp::Ordering;
+
+use super::{Gojo, Monoid, NodePtr};
+
+/// A key of the latest version, there or not, as `Gojo::entry` found it.
+/// Every change made through it creates one new version, derived from the
+/// latest one, without searching for the key again.
+pub enum Entry<'a, K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()> {
+    Vacant(VacantEntry<'a, K, V, MODS, M>),
+    Occupied(OccupiedEntry<'a, K, V, MODS, M>),
+}
+
+/// A key that is not in the latest version.
+pub struct VacantEntry<'a, K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()> {
+    gojo: &'a mut Gojo<K, V, MODS, M>,
+    key: K,
+    // Last node on the search path of the key, where it is linked
+    parent: NodePtr,
+}
+
+/// A key that is in the latest version.
+pub struct OccupiedEntry<'a, K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()>
+{
+    gojo: &'a mut Gojo<K, V, MODS, M>,
+    node: NodePtr,
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M> {
+    /// Entry of `k` in the latest version, to insert it or change it there.
+    pub fn entry(&mut self, k: K) -> Entry<'_, K, V, MODS, M> {
+        let version = self.curr_version;
+        let mut parent = NodePtr::null();
+        let mut x = self.get_root(version);
+        while !x.is_null() {
+            let next = match k.cmp(self.arena.key(x)) {
+                Ordering::Less => self.arena.left(x, version),
+                Ordering::Greater => self.arena.right(x, version),
+                Ordering::Equal => {
+                    return Entry::Occupied(OccupiedEntry {
+                        gojo: self,
+                        node: x,
+                    });
+                }
+            };
+            parent = x;
+            x = next;
+        }
+
+        Entry::Vacant(VacantEntry {
+            gojo: self,
+            key: k,
+            parent,
+        })
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Entry<'a, K, V, MODS, M> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `v` in a new version if the key is not there, no version is
+    /// created otherwise. Returns the value of the key in the latest version.
+    pub fn or_insert(self, v: V) -> &'a V {
+        self.or_insert_with(|| v)
+    }
+
+    /// Same as `or_insert`, `f` only runs when the key is not there.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> &'a V {
+        match self {
+            Entry::Vacant(entry) => entry.insert(f()),
+            Entry::Occupied(entry) => entry.into_ref(),
+        }
+    }
+
+    pub fn or_default(self) -> &'a V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Records `f` applied to the value in a new version if the key is there,
+    /// no version is created otherwise.
+    pub fn and_modify<F: FnOnce(&V) -> V>(self, f: F) -> Self {
+        match self {
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+            Entry::Occupied(mut entry) => {
+                let value = f(entry.get());
+                entry.insert(value);
+                Entry::Occupied(entry)
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>>
+    VacantEntry<'a, K, V, MODS, M>
+{
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts the key with `v` in a new version and returns the value there.
+    pub fn insert(self, v: V) -> &'a V {
+        let gojo = self.gojo;
+        let from = gojo.curr_version;
+        gojo.branch(from);
+        let node = gojo.link_below(self.parent, self.key, v);
+        gojo.publish(from);
+        gojo.arena.value_at(node, gojo.curr_version)
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>>
+    OccupiedEntry<'a, K, V, MODS, M>
+{
+    pub fn key(&self) -> &K {
+        self.gojo.arena.key(self.node)
+    }
+
+    /// Value of the key in the latest version.
+    pub fn get(&self) -> &V {
+        self.gojo.arena.value_at(self.node, self.gojo.curr_version)
+    }
+
+    /// Copies of the key in the latest version.
+    pub fn count(&self) -> usize {
+        self.gojo.arena.count(self.node, self.gojo.curr_version)
+    }
+
+    pub fn into_ref(self) -> &'a V {
+        self.gojo.arena.value_at(self.node, self.gojo.curr_version)
+    }
+
+    /// Records `v` as the value of the key in a new version, whatever the
+    /// duplicate policy is. Returns the value it had before.
+    pub fn insert(&mut self, v: V) -> V {
+        let from = self.gojo.curr_version;
+        let old = self.get().clone();
+        self.gojo.replace_value(from, self.node, v);
+        old
+    }
+
+    /// Removes the key in a new version, with every copy of it.
+    pub fn remove_entry(self) -> (K, V) {
+        let from = self.gojo.curr_version;
+        let key = self.gojo.arena.key(self.node).clone();
+        let value = self.gojo.remove_node(from, self.node);
+        (key, value)
+    }
+
+    /// Same as `remove_entry`, returning only the value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+}
+
+#[cfg(test)]
+mod entry_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::Entry;
+    use crate::gojo::{monoid::Sum, DuplicatePolicy, Gojo};
+    use anyhow::Result;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_or_insert_creates_a_version_only_for_a_vacant_key() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, &str> = Gojo::default();
+        gojo.insert(2, "two");
+
+        // Act
+        let inserted = *gojo.entry(1).or_insert("one");
+        let kept = *gojo.entry(2).or_insert("dos");
+
+        // Assert
+        assert_eq!("one", inserted);
+        assert_eq!("two", kept);
+        assert_eq!(2, gojo.latest_version());
+        assert_eq!(Some(1), gojo.parent_version(2));
+        assert_eq!(vec![(&2, &"two")], gojo.iter(1)?.collect::<Vec<_>>());
+        assert_eq!(
+            vec![(&1, &"one"), (&2, &"two")],
+            gojo.iter(2)?.collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_and_modify_or_insert_creates_a_single_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<&str, i32> = Gojo::default();
+
+        // Act
+        for word in ["a", "b", "a", "a"] {
+            gojo.entry(word).and_modify(|n| n + 1).or_insert(1);
+        }
+
+        // Assert
+        assert_eq!(4, gojo.latest_version());
+        assert_eq!(Some(&1), gojo.get(&"a", 1));
+        assert_eq!(Some(&2), gojo.get(&"a", 3));
+        assert_eq!(Some(&3), gojo.get(&"a", 4));
+        assert_eq!(Some(&1), gojo.get(&"b", 4));
+        assert!(gojo.validate_all().is_ok(), "{:?}", gojo.validate_all());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_occupied_entry_removes_every_copy() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::with_policy(10, DuplicatePolicy::Multi);
+        gojo.insert(1, 10);
+        gojo.insert(1, 10);
+        gojo.insert(2, 20);
+
+        // Act
+        let removed = match gojo.entry(1) {
+            Entry::Occupied(entry) => {
+                assert_eq!(2, entry.count());
+                entry.remove_entry()
+            }
+            Entry::Vacant(_) => unreachable!(),
+        };
+
+        // Assert
+        assert_eq!((1, 10), removed);
+        assert_eq!(4, gojo.latest_version());
+        assert_eq!(vec![(&2, &20)], gojo.iter(4)?.collect::<Vec<_>>());
+        assert!(matches!(gojo.entry(1), Entry::Vacant(entry) if *entry.key() == 1));
+        assert_eq!(4, gojo.latest_version());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entries_keep_summaries() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32, 6, Sum> = Gojo::with_mods(10, DuplicatePolicy::Reject);
+
+        // Act
+        for k in 0..20 {
+            gojo.entry(k).or_insert(k);
+        }
+        gojo.entry(5).and_modify(|v| v * 100);
+        if let Entry::Occupied(entry) = gojo.entry(7) {
+            entry.remove();
+        }
+
+        // Assert
+        assert_eq!(22, gojo.latest_version());
+        assert_eq!(190, gojo.aggregate(20, ..)?);
+        assert_eq!(685, gojo.aggregate(21, ..)?);
+        assert_eq!(678, gojo.aggregate(22, ..)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_entries_match_naive_snapshots() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(25);
+        let mut gojo: Gojo<i32, i32, 2> = Gojo::with_mods(10, DuplicatePolicy::Reject);
+        let mut snapshots = vec![BTreeMap::new()];
+
+        // Act
+        for _ in 0..500 {
+            let mut snapshot: BTreeMap<i32, i32> = snapshots.last().unwrap().clone();
+            let key = rng.gen_range(0..60);
+            let value = rng.gen_range(0..100);
+            match gojo.entry(key) {
+                Entry::Occupied(entry) if rng.gen_bool(0.5) => {
+                    assert_eq!(snapshot.remove(&key), Some(entry.remove()));
+                }
+                entry => {
+                    entry.and_modify(|v| v + value).or_insert(value);
+                    *snapshot.entry(key).or_insert(0) += value;
+                }
+            }
+            snapshots.push(snapshot);
+        }
+
+        // Assert
+        assert!(gojo.validate_all().is_ok(), "{:?}", gojo.validate_all());
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            let actual: Vec<(i32, i32)> = gojo.iter(version)?.map(|(k, v)| (*k, *v)).collect();
+            let expected: Vec<(i32, i32)> = snapshot.iter().map(|(k, v)| (*k, *v)).collect();
+            assert_eq!(expected, actual, "for version {version}");
+        }
+
+        Ok(())
+    }
+}
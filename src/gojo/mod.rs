@@ -1,22 +1,53 @@
 use anyhow::Result;
+use arrayvec::ArrayVec;
+use monoid::Monoid;
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::cmp::Ord;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt::{self, Debug};
-use std::marker;
-use std::ptr;
-use std::ptr::drop_in_place;
+#[cfg(feature = "serde")]
+use std::io::{Read, Write};
+use std::iter::FusedIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
 use thiserror::Error;
+use version_list::VersionList;
 
+pub mod batch;
 pub mod cli;
+mod dot;
+pub mod entry;
+pub mod monoid;
 pub mod parser;
+pub mod retroactive;
+pub mod set;
+pub mod shared;
+mod version_list;
+#[cfg(feature = "serde")]
+pub mod wal;
 
-const MAX_MODS: usize = 6;
 const DEFAULT_MAX_OPS: usize = 100;
 
 #[derive(Error, Debug)]
 pub enum GojoError {
     #[error("the version `{0}` is not available")]
     UnknownVersion(String),
+    #[error("the version `{0}` was pruned from history")]
+    VersionPruned(String),
+    #[error("the label `{0}` is already used by another version")]
+    LabelInUse(String),
+    #[error("the timestamp `{0}` is out of order with the ones of other versions")]
+    TimestampOutOfOrder(String),
+    #[error("the key at position `{0}` is smaller than the one before it")]
+    UnsortedKeys(String),
+    #[error("broken invariant at {0}")]
+    BrokenInvariant(String),
+    #[error("the write-ahead log is corrupted at byte `{0}`")]
+    CorruptedLog(String),
+    #[error("the write-ahead log failed to cut off a torn record and takes no more")]
+    FailedLog,
     #[error("cannot convert null node to NodeInfo")]
     ForbiddenConvertionToNodeInfo,
     #[error("unknown gojo error")]
@@ -24,53 +55,104 @@ pub enum GojoError {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Color {
     #[default]
     Red,
     Black,
 }
 
+/// What `insert` does with a key that is already there. The default is
+/// `Multi`, so that every insert creates a version and keeps the duplicates,
+/// as it always did.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DuplicatePolicy {
+    /// Keeps the key as it is, no version is created.
+    Reject,
+    /// Records the new value in a new version.
+    Replace,
+    /// Counts one more copy of the key in a new version, keeping its value.
+    /// `remove` takes one copy away at a time.
+    #[default]
+    Multi,
+}
+
+/// What an insert did, telling whether a new version was created.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InsertOutcome {
+    /// The key was not there, a new version holds it.
+    Inserted,
+    /// The key was there, a new version holds its new value.
+    Replaced,
+    /// The key was there, a new version holds one more copy of it.
+    Counted,
+    /// The key was there and was kept as it is, no version was created.
+    Rejected,
+}
+
+impl InsertOutcome {
+    pub fn created_version(&self) -> bool {
+        *self != InsertOutcome::Rejected
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum ModData<K: Ord + Clone + Default, V: Clone + Default> {
-    Parent(NodePtr<K, V>),
-    Left(NodePtr<K, V>),
-    Right(NodePtr<K, V>),
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum ModData<V: Clone, S: Clone> {
+    Parent(NodePtr),
+    Left(NodePtr),
+    Right(NodePtr),
     Col(Color),
+    Size(usize),
+    Count(usize),
+    Stamp(usize),
+    Value(V),
+    Agg(S),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-struct Mod<K: Ord + Clone + Default, V: Clone + Default> {
-    data: ModData<K, V>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Mod<V: Clone, S: Clone> {
+    data: ModData<V, S>,
     version: usize,
 }
 
-impl<K: Ord + Clone + Default, V: Clone + Default> Mod<K, V> {
+impl<V: Clone, S: Clone> Mod<V, S> {
     #[allow(dead_code)]
-    fn new(data: ModData<K, V>, version: usize) -> Self {
+    fn new(data: ModData<V, S>, version: usize) -> Self {
         Self { data, version }
     }
 }
 
 #[derive(PartialEq, Eq, Clone)]
-struct GojoNode<K: Ord + Clone + Default, V: Clone + Default> {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct GojoNode<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V> = ()> {
     color: Color,
-    left: NodePtr<K, V>,
-    right: NodePtr<K, V>,
-    parent: NodePtr<K, V>,
-    bk_ptr_left: NodePtr<K, V>,
-    bk_ptr_right: NodePtr<K, V>,
-    bk_ptr_parent: NodePtr<K, V>,
+    left: NodePtr,
+    right: NodePtr,
+    parent: NodePtr,
+    bk_ptr_left: NodePtr,
+    bk_ptr_right: NodePtr,
+    bk_ptr_parent: NodePtr,
     key: K,
     value: V,
-    mods: Vec<Mod<K, V>>,
-    next_copy: NodePtr<K, V>,
+    size: usize,
+    count: usize,
+    // Last version that changed something in the subtree of the node
+    stamp: usize,
+    // Summary of the keys and values in the subtree of the node
+    agg: M::Summary,
+    mods: ArrayVec<Mod<V, M::Summary>, MODS>,
+    next_copy: NodePtr,
     version: usize,
+    until: Option<usize>,
 }
 
-impl<K, V> Debug for GojoNode<K, V>
+impl<K, V, const MODS: usize, M: Monoid<K, V>> Debug for GojoNode<K, V, MODS, M>
 where
-    K: Ord + Clone + Default + Debug,
-    V: Clone + Default + Debug,
+    K: Ord + Clone + Debug,
+    V: Clone + Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("GojoNode")
@@ -82,38 +164,287 @@ where
     }
 }
 
-impl<K: Ord + Clone + Default, V: Clone + Default> GojoNode<K, V> {
-    fn clone_with_latest_mods(&self) -> Self {
-        let key = self.key.clone();
-        let value = self.value.clone();
-        let mods = Vec::with_capacity(MAX_MODS);
-        let next_copy = NodePtr::null();
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> GojoNode<K, V, MODS, M> {
+    fn new(key: K, value: V) -> Self {
+        let agg = M::lift(&key, &value);
+        Self {
+            color: Color::Red,
+            left: NodePtr::null(),
+            right: NodePtr::null(),
+            parent: NodePtr::null(),
+            bk_ptr_left: NodePtr::null(),
+            bk_ptr_right: NodePtr::null(),
+            bk_ptr_parent: NodePtr::null(),
+            key,
+            value,
+            size: 1,
+            count: 1,
+            stamp: 0,
+            agg,
+            mods: ArrayVec::new(),
+            next_copy: NodePtr::null(),
+            version: 0,
+            until: None,
+        }
+    }
+}
+
+/// Index of a `GojoNode` inside the arena of the `Gojo` that owns it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodePtr(u32);
+
+impl Debug for NodePtr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_null() {
+            true => f.debug_tuple("NodePtr").field(&"nilson").finish(),
+            false => f.debug_tuple("NodePtr").field(&self.0).finish(),
+        }
+    }
+}
+
+impl NodePtr {
+    fn null() -> NodePtr {
+        NodePtr(u32::MAX)
+    }
+
+    fn is_null(&self) -> bool {
+        self.0 == u32::MAX
+    }
+
+    fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Owns every `GojoNode` of a `Gojo`, copies made by the fat nodes included,
+/// together with the list that orders their versions.
+///
+/// Nodes are never freed one by one, as older versions may still reach them,
+/// so dropping the arena frees the nodes of every version at once.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "K: Serialize, V: Serialize, M::Summary: Serialize",
+        deserialize = "K: Deserialize<'de>, V: Deserialize<'de>, M::Summary: Deserialize<'de>"
+    ))
+)]
+struct Arena<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V> = ()> {
+    nodes: Vec<GojoNode<K, V, MODS, M>>,
+    versions: VersionList,
+    // Nodes changed by the update in progress, empty between updates
+    #[cfg_attr(feature = "serde", serde(skip))]
+    touched: Vec<NodePtr>,
+    // Index over the chains of copies, derived from `next_copy`: the chain of
+    // every node, `NO_CHAIN` for a node never copied, the copy every copy is
+    // nested in, the chains themselves and the copies starting at every
+    // version, to sort them again when the version list is relabeled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    chain_of: Vec<u32>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    enclosing: Vec<NodePtr>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    chains: Vec<CopyChain>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    starting_at: Vec<Vec<NodePtr>>,
+}
+
+const NO_CHAIN: u32 = u32::MAX;
+// Copies walked from a node before searching the index of its chain
+const SHORT_CHAIN: usize = 8;
+
+// A node and its copies
+#[derive(Clone, Debug)]
+struct CopyChain {
+    head: NodePtr,
+    // Newest copy, the end of the `next_copy` chain
+    last: NodePtr,
+    // Sorted by the label of the version they start at, then by creation
+    copies: BTreeMap<(u64, u32), NodePtr>,
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Arena<K, V, MODS, M> {
+    fn new(versions: VersionList) -> Arena<K, V, MODS, M> {
+        Arena {
+            nodes: Vec::new(),
+            versions,
+            touched: Vec::new(),
+            chain_of: Vec::new(),
+            enclosing: Vec::new(),
+            chains: Vec::new(),
+            starting_at: Vec::new(),
+        }
+    }
 
-        let mut color = self.color;
-        let mut left = self.left;
-        let mut right = self.right;
-        let mut parent = self.parent;
+    fn alloc(&mut self, node: GojoNode<K, V, MODS, M>) -> NodePtr {
+        let index = u32::try_from(self.nodes.len())
+            .ok()
+            .filter(|index| *index != u32::MAX)
+            .expect("the gojo arena can not hold more nodes");
+        self.nodes.push(node);
+        self.chain_of.push(NO_CHAIN);
+        self.enclosing.push(NodePtr::null());
+        NodePtr(index)
+    }
 
-        for m in self.mods.iter() {
-            match m.data {
-                ModData::Parent(p) => parent = p,
-                ModData::Left(l) => left = l,
-                ModData::Right(r) => right = r,
-                ModData::Col(c) => color = c,
+    /// Builds the index over the chains of copies again from `next_copy`.
+    fn index_copies(&mut self) {
+        self.chain_of = vec![NO_CHAIN; self.nodes.len()];
+        self.enclosing = vec![NodePtr::null(); self.nodes.len()];
+        self.chains.clear();
+        self.starting_at.clear();
+        let mut is_copy = vec![false; self.nodes.len()];
+        for node in self.nodes.iter() {
+            if !node.next_copy.is_null() {
+                is_copy[node.next_copy.index()] = true;
+            }
+        }
+        for head in (0..self.nodes.len()).filter(|index| !is_copy[*index]) {
+            let head = NodePtr(head as u32);
+            let chain = self.chains.len() as u32;
+            let mut copies = BTreeMap::new();
+            let mut last = head;
+            let mut caba = self.get_next_copy(head);
+            while !caba.is_null() {
+                self.chain_of[caba.index()] = chain;
+                self.start_copy(caba);
+                copies.insert(self.copy_key(caba), caba);
+                last = caba;
+                caba = self.get_next_copy(caba);
             }
+            if copies.is_empty() {
+                continue;
+            }
+            self.chain_of[head.index()] = chain;
+
+            // A copy is nested in the last one before it, in start order, that
+            // owns the version it starts at
+            let mut open: Vec<NodePtr> = Vec::new();
+            for &copy in copies.values() {
+                let start = self.node(copy).version;
+                while open.last().is_some_and(|last| !self.covers(*last, start)) {
+                    open.pop();
+                }
+                self.enclosing[copy.index()] = open.last().copied().unwrap_or(head);
+                open.push(copy);
+            }
+            self.chains.push(CopyChain { head, last, copies });
+        }
+    }
+
+    // Key of a copy in the index of its chain
+    fn copy_key(&self, copy: NodePtr) -> (u64, u32) {
+        (self.versions.label(self.node(copy).version), copy.0)
+    }
+
+    // Records `copy` among the copies starting at its version
+    fn start_copy(&mut self, copy: NodePtr) {
+        let at = self.node(copy).version - self.versions.first();
+        if self.starting_at.len() <= at {
+            self.starting_at.resize(at + 1, Vec::new());
         }
+        self.starting_at[at].push(copy);
+    }
 
-        let version = match self.mods.last() {
-            Some(m) => m.version,
-            None => self.version,
-        };
+    /// Inserts a new version right after `from` in the version list, moving
+    /// the copies starting at the versions relabeled for it in their chains.
+    fn insert_version(&mut self, from: usize) -> usize {
+        let (version, relabeled) = self.versions.insert_after(from);
+        for (moved, old_label) in relabeled {
+            let Some(copies) = self.starting_at.get(moved - self.versions.first()) else {
+                continue;
+            };
+            for &copy in copies.iter() {
+                let chain = &mut self.chains[self.chain_of[copy.index()] as usize];
+                chain.copies.remove(&(old_label, copy.0));
+                chain
+                    .copies
+                    .insert((self.versions.label(moved), copy.0), copy);
+            }
+        }
+        version
+    }
+
+    fn node(&self, ptr: NodePtr) -> &GojoNode<K, V, MODS, M> {
+        &self.nodes[ptr.index()]
+    }
+
+    fn node_mut(&mut self, ptr: NodePtr) -> &mut GojoNode<K, V, MODS, M> {
+        &mut self.nodes[ptr.index()]
+    }
+
+    /// Whether a change made at `change` is visible from `version`.
+    fn sees(&self, change: usize, version: usize) -> bool {
+        self.versions.cmp(change, version) != Ordering::Greater
+    }
+
+    /// Whether this node (or copy) is the one that owns `version`.
+    fn covers(&self, ptr: NodePtr, version: usize) -> bool {
+        let node = self.node(ptr);
+        self.sees(node.version, version)
+            && node.until.is_none_or(|until| !self.sees(until, version))
+    }
+
+    /// Whether both pointers lead to the same node as seen from `version`.
+    fn same(&self, a: NodePtr, b: NodePtr, version: usize) -> bool {
+        self.get_last_copy(a, version) == self.get_last_copy(b, version)
+    }
+
+    fn key(&self, ptr: NodePtr) -> &K {
+        &self.node(ptr).key
+    }
+
+    /// Copies the node as seen from `version`, owning only that version and
+    /// the ones derived from it.
+    fn clone_at(&self, ptr: NodePtr, version: usize) -> GojoNode<K, V, MODS, M> {
+        let node = self.node(ptr);
+        let key = node.key.clone();
+        let mut value = &node.value;
+        let mods = ArrayVec::new();
+        let next_copy = NodePtr::null();
+
+        let mut color = node.color;
+        let mut left = node.left;
+        let mut right = node.right;
+        let mut parent = node.parent;
+        let mut size = node.size;
+        let mut count = node.count;
+        let mut stamp = node.stamp;
+        let mut agg = &node.agg;
+
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            match &m.data {
+                ModData::Parent(p) => parent = *p,
+                ModData::Left(l) => left = *l,
+                ModData::Right(r) => right = *r,
+                ModData::Col(c) => color = *c,
+                ModData::Size(n) => size = *n,
+                ModData::Count(n) => count = *n,
+                ModData::Stamp(n) => stamp = *n,
+                ModData::Value(v) => value = v,
+                ModData::Agg(a) => agg = a,
+            }
+        }
+
+        let value = value.clone();
+        let agg = agg.clone();
+        let until = self.versions.next(version);
         let bk_ptr_left = left;
         let bk_ptr_right = right;
         let bk_ptr_parent = parent;
-        Self {
+        GojoNode {
             color,
             key,
             value,
+            size,
+            count,
+            stamp,
+            agg,
             mods,
             left,
             right,
@@ -123,458 +454,918 @@ impl<K: Ord + Clone + Default, V: Clone + Default> GojoNode<K, V> {
             bk_ptr_parent,
             next_copy,
             version,
+            until,
         }
     }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> Default for GojoNode<K, V> {
-    fn default() -> Self {
-        Self {
-            color: Default::default(),
-            left: NodePtr::null(),
-            right: NodePtr::null(),
-            parent: NodePtr::null(),
-            bk_ptr_left: NodePtr::null(),
-            bk_ptr_right: NodePtr::null(),
-            bk_ptr_parent: NodePtr::null(),
-            key: Default::default(),
-            value: Default::default(),
-            mods: Default::default(),
-            next_copy: NodePtr::null(),
-            version: 0,
+    /// Value of the field changed by `data` as seen from `version`.
+    fn field_at(
+        &self,
+        ptr: NodePtr,
+        data: &ModData<V, M::Summary>,
+        version: usize,
+    ) -> ModData<V, M::Summary> {
+        let node = self.node(ptr);
+        let mut value = match data {
+            ModData::Parent(_) => ModData::Parent(node.parent),
+            ModData::Left(_) => ModData::Left(node.left),
+            ModData::Right(_) => ModData::Right(node.right),
+            ModData::Col(_) => ModData::Col(node.color),
+            ModData::Size(_) => ModData::Size(node.size),
+            ModData::Count(_) => ModData::Count(node.count),
+            ModData::Stamp(_) => ModData::Stamp(node.stamp),
+            ModData::Value(_) => ModData::Value(node.value.clone()),
+            ModData::Agg(_) => ModData::Agg(node.agg.clone()),
+        };
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if mem::discriminant(&m.data) == mem::discriminant(data) {
+                value = m.data.clone();
+            }
         }
+        value
     }
-}
-
-pub struct NodePtr<K: Ord + Clone + Default, V: Clone + Default> {
-    pointer: *mut GojoNode<K, V>,
-    null: bool,
-}
 
-impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Debug for NodePtr<K, V> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.is_null() {
-            true => f
-                .debug_struct("NodePtr")
-                .field("pointer", &"nilson")
-                .field("null", &self.null)
-                .finish(),
-            false => unsafe {
-                let res = &(*self.pointer);
-                f.debug_struct("NodePtr")
-                    .field("pointer", res)
-                    .field("null", &self.null)
-                    .finish()
-            },
+    fn set_color(&mut self, ptr: NodePtr, color: Color, version: usize) {
+        if ptr.is_null() {
+            return;
         }
-    }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> Clone for NodePtr<K, V> {
-    fn clone(&self) -> NodePtr<K, V> {
-        *self
-    }
-}
+        let ptr = self.get_last_copy(ptr, version);
 
-impl<K: Ord + Clone + Default, V: Clone + Default> Copy for NodePtr<K, V> {}
+        let curr_color = self.get_color(ptr, version);
+        if color == curr_color {
+            return;
+        }
 
-impl<K: Ord + Clone + Default, V: Clone + Default> Ord for NodePtr<K, V> {
-    fn cmp(&self, other: &NodePtr<K, V>) -> Ordering {
-        unsafe { (*self.pointer).key.cmp(&(*other.pointer).key) }
+        self.set_modification(ptr, ModData::Col(color), version);
     }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> PartialOrd for NodePtr<K, V> {
-    fn partial_cmp(&self, other: &NodePtr<K, V>) -> Option<Ordering> {
-        unsafe { Some((*self.pointer).key.cmp(&(*other.pointer).key)) }
+    fn set_red_color(&mut self, ptr: NodePtr, version: usize) {
+        self.set_color(ptr, Color::Red, version);
     }
 
-    fn lt(&self, other: &Self) -> bool {
-        matches!(self.partial_cmp(other), Some(Ordering::Less))
+    fn set_black_color(&mut self, ptr: NodePtr, version: usize) {
+        self.set_color(ptr, Color::Black, version);
     }
 
-    fn le(&self, other: &Self) -> bool {
-        matches!(
-            self.partial_cmp(other),
-            Some(Ordering::Less | Ordering::Equal)
-        )
+    /// Amount of nodes in the subtree of `ptr` as seen from `version`.
+    fn size(&self, ptr: NodePtr, version: usize) -> usize {
+        if ptr.is_null() {
+            return 0;
+        }
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.size;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Size(d) = m.data {
+                value = d;
+            }
+        }
+        value
     }
 
-    fn gt(&self, other: &Self) -> bool {
-        matches!(self.partial_cmp(other), Some(Ordering::Greater))
+    /// Copies of the key of `ptr` as seen from `version`.
+    fn count(&self, ptr: NodePtr, version: usize) -> usize {
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.count;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Count(d) = m.data {
+                value = d;
+            }
+        }
+        value
     }
 
-    fn ge(&self, other: &Self) -> bool {
-        matches!(
-            self.partial_cmp(other),
-            Some(Ordering::Greater | Ordering::Equal)
-        )
+    fn set_count(&mut self, ptr: NodePtr, count: usize, version: usize) {
+        let ptr = self.get_last_copy(ptr, version);
+        self.set_modification(ptr, ModData::Count(count), version);
     }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> PartialEq for NodePtr<K, V> {
-    fn eq(&self, other: &NodePtr<K, V>) -> bool {
-        unsafe {
-            if other.is_null() && self.is_null() {
-                return true;
+    /// Last version that changed the subtree of `ptr` as seen from `version`.
+    /// Two versions that see the same copy with the same stamp see the same
+    /// subtree.
+    fn stamp(&self, ptr: NodePtr, version: usize) -> usize {
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.stamp;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
             }
-            if other.is_null() || self.is_null() {
-                return false;
+            if let ModData::Stamp(d) = m.data {
+                value = d;
             }
-            (*other.pointer).key == (*self.pointer).key
         }
+        value
     }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> Eq for NodePtr<K, V> {}
+    fn set_stamp(&mut self, ptr: NodePtr, version: usize) {
+        let ptr = self.get_last_copy(ptr, version);
+        self.set_modification(ptr, ModData::Stamp(version), version);
+    }
 
-impl<K: Ord + Clone + Default, V: Clone + Default> From<GojoNode<K, V>> for NodePtr<K, V> {
-    fn from(value: GojoNode<K, V>) -> Self {
-        let ptr = Box::into_raw(Box::new(value));
+    // Sets the size and the summary of `ptr` from the ones of its children
+    fn update_size(&mut self, ptr: NodePtr, version: usize) {
+        if ptr.is_null() {
+            return;
+        }
 
-        NodePtr {
-            pointer: ptr,
-            null: false,
+        let ptr = self.get_last_copy(ptr, version);
+        let size = self.size(self.left(ptr, version), version)
+            + self.size(self.right(ptr, version), version)
+            + 1;
+        if size != self.size(ptr, version) {
+            self.set_modification(ptr, ModData::Size(size), version);
         }
+        self.update_agg(ptr, version);
     }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> NodePtr<K, V> {
-    fn new(k: K, v: V) -> NodePtr<K, V> {
-        let node = GojoNode {
-            key: k,
-            value: v,
-            ..Default::default()
-        };
-        NodePtr {
-            pointer: Box::into_raw(Box::new(node)),
-            null: false,
+    /// Summary of the subtree of `ptr` as seen from `version`.
+    fn agg(&self, ptr: NodePtr, version: usize) -> M::Summary {
+        if ptr.is_null() {
+            return M::empty();
         }
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = &node.agg;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Agg(d) = &m.data {
+                value = d;
+            }
+        }
+        value.clone()
     }
 
-    fn set_color(&mut self, color: Color, version: usize) {
-        if self.is_null() {
+    // Sets the summary of `ptr` from the ones of its children. Without a
+    // summary to keep, or when it did not change, no mod is spent on it
+    fn update_agg(&mut self, ptr: NodePtr, version: usize) {
+        if ptr.is_null() || mem::size_of::<M::Summary>() == 0 {
             return;
         }
 
-        let mut ptr = self.get_last_copy(version);
-
-        let curr_color = ptr.get_color(version);
-        if color == curr_color {
-            return;
+        let ptr = self.get_last_copy(ptr, version);
+        let own = M::lift(self.key(ptr), self.value_at(ptr, version));
+        let left = self.agg(self.left(ptr, version), version);
+        let right = self.agg(self.right(ptr, version), version);
+        let agg = M::combine(&M::combine(&left, &own), &right);
+        if agg != self.agg(ptr, version) {
+            self.set_modification(ptr, ModData::Agg(agg), version);
         }
+    }
 
-        unsafe {
-            let new_mod = ModData::Col(color);
-            ptr.set_modification(new_mod, version);
-        }
+    #[allow(dead_code)]
+    fn get_value(&self, ptr: NodePtr) -> V {
+        self.node(ptr).value.clone()
     }
 
-    fn set_red_color(&mut self, version: usize) {
-        self.set_color(Color::Red, version);
+    /// Value of the node as seen from `version`.
+    fn value_at(&self, ptr: NodePtr, version: usize) -> &V {
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = &node.value;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Value(d) = &m.data {
+                value = d;
+            }
+        }
+        value
     }
 
-    fn set_black_color(&mut self, version: usize) {
-        self.set_color(Color::Black, version);
+    fn set_value(&mut self, ptr: NodePtr, value: V, version: usize) {
+        let ptr = self.get_last_copy(ptr, version);
+        self.set_modification(ptr, ModData::Value(value), version);
     }
 
-    #[allow(dead_code)]
-    fn get_value(&self) -> V {
-        unsafe { (*self.pointer).value.clone() }
+    fn get_next_copy(&self, ptr: NodePtr) -> NodePtr {
+        self.node(ptr).next_copy
     }
 
-    unsafe fn get_next_copy(&self) -> NodePtr<K, V> {
-        (*self.pointer).next_copy
+    fn get_last_copy(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        self.find_copy(ptr, version).0
     }
 
-    fn get_last_copy(&self, version: usize) -> NodePtr<K, V> {
-        if self.is_null() {
-            return *self;
+    // Copy of the node of `ptr` that owns `version`, with the amount of copies
+    // looked at to find it. Every copy owns a branch of the version tree
+    // nested in the one of the copy it was made from, so the last copy that
+    // starts before the version owns it or is nested in the one that does
+    fn find_copy(&self, ptr: NodePtr, version: usize) -> (NodePtr, usize) {
+        if ptr.is_null() {
+            return (ptr, 0);
         }
-        let mut caba = *self;
-        unsafe {
-            while !caba.get_next_copy().is_null() && caba.get_next_copy().version() <= version {
-                caba = caba.get_next_copy();
+        // Copies are chained in creation order and `ptr` is the first node or
+        // a copy owning the version, so the newest copy after it owning the
+        // version too is nested in it and is the right one, found by walking
+        // when the chain ends soon enough
+        let mut last = ptr;
+        let mut caba = self.get_next_copy(ptr);
+        let mut looked = 0;
+        while !caba.is_null() && looked < SHORT_CHAIN {
+            looked += 1;
+            if self.covers(caba, version) {
+                last = caba;
             }
+            caba = self.get_next_copy(caba);
+        }
+        if caba.is_null() {
+            return (last, looked);
+        }
+
+        // Otherwise the index of the chain is searched, a node with copies
+        // always has one
+        let chain = &self.chains[self.chain_of[ptr.index()] as usize];
+        let mut looked = looked + 1;
+        let mut copy = match chain
+            .copies
+            .range(..=(self.versions.label(version), u32::MAX))
+            .next_back()
+        {
+            Some((_, copy)) => *copy,
+            None => chain.head,
+        };
+        while copy != chain.head && !self.covers(copy, version) {
+            looked += 1;
+            copy = self.enclosing[copy.index()];
         }
-        caba
+        (copy, looked)
     }
 
-    pub fn get_color(&self, version: usize) -> Color {
-        if self.is_null() {
+    fn get_color(&self, ptr: NodePtr, version: usize) -> Color {
+        if ptr.is_null() {
             return Color::Black;
         }
-        unsafe {
-            let ptr = self.get_last_copy(version);
-            let mut value = (*ptr.pointer).color;
-            for m in (*ptr.pointer).mods.iter() {
-                if m.version > version {
-                    break;
-                }
-                if let ModData::Col(d) = m.data {
-                    value = d;
-                }
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.color;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Col(d) = m.data {
+                value = d;
             }
-            value
         }
+        value
     }
 
-    fn version(&self) -> usize {
-        unsafe { (*self.pointer).version }
+    #[allow(dead_code)]
+    fn version(&self, ptr: NodePtr) -> usize {
+        self.node(ptr).version
     }
 
-    fn is_red_color(&self, version: usize) -> bool {
-        if self.is_null() {
+    fn is_red_color(&self, ptr: NodePtr, version: usize) -> bool {
+        if ptr.is_null() {
             return false;
         }
-        let color = self.get_color(version);
+        let color = self.get_color(ptr, version);
         color == Color::Red
     }
 
-    fn is_black_color(&self, version: usize) -> bool {
-        if self.is_null() {
+    fn is_black_color(&self, ptr: NodePtr, version: usize) -> bool {
+        if ptr.is_null() {
             return true;
         }
-        let color = self.get_color(version);
+        let color = self.get_color(ptr, version);
         color == Color::Black
     }
 
-    fn is_left_child(&self, version: usize) -> bool {
-        let ptr = self.get_last_copy(version);
-        let other = ptr.parent(version).left(version);
+    fn is_left_child(&self, ptr: NodePtr, version: usize) -> bool {
+        let ptr = self.get_last_copy(ptr, version);
+        let other = self.left(self.parent(ptr, version), version);
         other == ptr
     }
 
-    fn is_right_child(&self, version: usize) -> bool {
-        let ptr = self.get_last_copy(version);
-        let other = ptr.parent(version).right(version);
+    fn is_right_child(&self, ptr: NodePtr, version: usize) -> bool {
+        let ptr = self.get_last_copy(ptr, version);
+        let other = self.right(self.parent(ptr, version), version);
         other == ptr
     }
 
-    fn min_node(self, version: usize) -> NodePtr<K, V> {
-        let mut temp = self;
-        while !temp.left(version).is_null() {
-            temp = temp.left(version);
+    fn min_node(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        let mut temp = ptr;
+        while !self.left(temp, version).is_null() {
+            temp = self.left(temp, version);
         }
         temp
     }
 
-    fn max_node(self, version: usize) -> NodePtr<K, V> {
-        let mut temp = self;
-        while !temp.right(version).is_null() {
-            temp = temp.right(version);
+    fn max_node(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        let mut temp = ptr;
+        while !self.right(temp, version).is_null() {
+            temp = self.right(temp, version);
         }
         temp
     }
 
-    unsafe fn set_modification(&mut self, mod_data: ModData<K, V>, version: usize) {
-        if self.is_null() {
+    fn set_modification(&mut self, ptr: NodePtr, mod_data: ModData<V, M::Summary>, version: usize) {
+        if ptr.is_null() {
+            return;
+        }
+        self.touched.push(ptr);
+
+        // A field changed again by the same version keeps a single mod
+        let same = self.node(ptr).mods.iter().position(|m| {
+            m.version == version && mem::discriminant(&m.data) == mem::discriminant(&mod_data)
+        });
+        if let Some(idx) = same {
+            let node = self.node_mut(ptr);
             match mod_data {
-                ModData::Parent(p) => (*self.pointer).parent = p,
-                ModData::Left(l) => (*self.pointer).left = l,
-                ModData::Right(r) => (*self.pointer).right = r,
-                ModData::Col(c) => (*self.pointer).color = c,
+                ModData::Parent(p) => node.bk_ptr_parent = p,
+                ModData::Left(l) => node.bk_ptr_left = l,
+                ModData::Right(r) => node.bk_ptr_right = r,
+                ModData::Col(_)
+                | ModData::Size(_)
+                | ModData::Count(_)
+                | ModData::Stamp(_)
+                | ModData::Value(_)
+                | ModData::Agg(_) => (),
             }
+            node.mods[idx].data = mod_data;
             return;
         }
 
-        if (*self.pointer).mods.len() < MAX_MODS {
+        // Versions after this one in the list that are not derived from it
+        // must keep seeing the old value, so the next one gets it restored
+        let restore = match self.versions.next(version) {
+            Some(next) if Some(next) != self.node(ptr).until => {
+                let already_set = self.node(ptr).mods.iter().any(|m| {
+                    m.version == next && mem::discriminant(&m.data) == mem::discriminant(&mod_data)
+                });
+                match already_set {
+                    true => None,
+                    false => Some(Mod {
+                        data: self.field_at(ptr, &mod_data, version),
+                        version: next,
+                    }),
+                }
+            }
+            _ => None,
+        };
+        let needed_mods = if restore.is_some() { 2 } else { 1 };
+
+        if self.node(ptr).mods.len() + needed_mods <= MODS {
+            let idx = self
+                .node(ptr)
+                .mods
+                .iter()
+                .position(|m| !self.sees(m.version, version))
+                .unwrap_or(self.node(ptr).mods.len());
+            let node = self.node_mut(ptr);
             match mod_data {
-                ModData::Parent(p) => (*self.pointer).bk_ptr_parent = p,
-                ModData::Left(l) => (*self.pointer).bk_ptr_left = l,
-                ModData::Right(r) => (*self.pointer).bk_ptr_right = r,
-                ModData::Col(_) => (),
-            }
-            (*self.pointer).mods.push(Mod {
-                data: mod_data,
-                version,
-            });
+                ModData::Parent(p) => node.bk_ptr_parent = p,
+                ModData::Left(l) => node.bk_ptr_left = l,
+                ModData::Right(r) => node.bk_ptr_right = r,
+                ModData::Col(_)
+                | ModData::Size(_)
+                | ModData::Count(_)
+                | ModData::Stamp(_)
+                | ModData::Value(_)
+                | ModData::Agg(_) => (),
+            }
+            node.mods.insert(
+                idx,
+                Mod {
+                    data: mod_data,
+                    version,
+                },
+            );
+            if let Some(restore) = restore {
+                node.mods.insert(idx + 1, restore);
+            }
             return;
         }
 
-        // Create a new node with all mods and the new change right here
-        let mut new_gojo_node = (*self.pointer).clone_with_latest_mods();
-        new_gojo_node.version = version;
-
-        let new_node_ptr = NodePtr {
-            pointer: Box::into_raw(Box::new(new_gojo_node)),
-            null: false,
-        };
-        (*self.pointer).next_copy = new_node_ptr;
-
+        // Create a new node with the mods seen by this version and the new
+        // change right here
+        let mut new_gojo_node = self.clone_at(ptr, version);
         match mod_data {
             ModData::Parent(p) => {
-                (*new_node_ptr.pointer).parent = p;
-                (*new_node_ptr.pointer).bk_ptr_parent = p;
+                new_gojo_node.parent = p;
+                new_gojo_node.bk_ptr_parent = p;
             }
             ModData::Left(l) => {
-                (*new_node_ptr.pointer).left = l;
-                (*new_node_ptr.pointer).bk_ptr_left = l;
+                new_gojo_node.left = l;
+                new_gojo_node.bk_ptr_left = l;
             }
             ModData::Right(r) => {
-                (*new_node_ptr.pointer).right = r;
-                (*new_node_ptr.pointer).bk_ptr_right = r;
+                new_gojo_node.right = r;
+                new_gojo_node.bk_ptr_right = r;
             }
-            ModData::Col(c) => (*new_node_ptr.pointer).color = c,
+            ModData::Col(c) => new_gojo_node.color = c,
+            ModData::Size(n) => new_gojo_node.size = n,
+            ModData::Count(n) => new_gojo_node.count = n,
+            ModData::Stamp(n) => new_gojo_node.stamp = n,
+            ModData::Value(v) => new_gojo_node.value = v,
+            ModData::Agg(a) => new_gojo_node.agg = a,
         }
 
-        // Update left back pontairos
-        let mut bk_ptr_left = (*new_node_ptr.pointer).bk_ptr_left;
-        if !bk_ptr_left.is_null() && bk_ptr_left.is_left_child(version) {
-            bk_ptr_left.set_parent(new_node_ptr, version);
+        let new_node_ptr = self.alloc(new_gojo_node);
+        let chain = match self.chain_of[ptr.index()] {
+            NO_CHAIN => {
+                self.chain_of[ptr.index()] = self.chains.len() as u32;
+                self.chains.push(CopyChain {
+                    head: ptr,
+                    last: ptr,
+                    copies: BTreeMap::new(),
+                });
+                self.chains.len() - 1
+            }
+            chain => chain as usize,
+        };
+        let last_copy = self.chains[chain].last;
+        self.node_mut(last_copy).next_copy = new_node_ptr;
+        self.chain_of[new_node_ptr.index()] = chain as u32;
+        self.enclosing[new_node_ptr.index()] = ptr;
+        let key = self.copy_key(new_node_ptr);
+        self.chains[chain].copies.insert(key, new_node_ptr);
+        self.chains[chain].last = new_node_ptr;
+        self.start_copy(new_node_ptr);
+
+        // Update left back pontairos, of a child that still has this node as
+        // parent, a node out of the tree keeps pointing to its old children
+        let bk_ptr_left = self.node(new_node_ptr).bk_ptr_left;
+        if !bk_ptr_left.is_null()
+            && self.is_left_child(bk_ptr_left, version)
+            && self.same(self.parent(bk_ptr_left, version), ptr, version)
+        {
+            self.set_parent(bk_ptr_left, new_node_ptr, version);
         }
 
         // Update right back pontairos
-        let mut bk_ptr_right = (*new_node_ptr.pointer).bk_ptr_right;
-        if !bk_ptr_right.is_null() && bk_ptr_right.is_right_child(version) {
-            bk_ptr_right.set_parent(new_node_ptr, version);
+        let bk_ptr_right = self.node(new_node_ptr).bk_ptr_right;
+        if !bk_ptr_right.is_null()
+            && self.is_right_child(bk_ptr_right, version)
+            && self.same(self.parent(bk_ptr_right, version), ptr, version)
+        {
+            self.set_parent(bk_ptr_right, new_node_ptr, version);
         }
 
-        let mut bk_ptr_parent = (*new_node_ptr.pointer).bk_ptr_parent;
+        let bk_ptr_parent = self.node(new_node_ptr).bk_ptr_parent;
         // We got a new root boys
         if bk_ptr_parent.is_null() {
             return;
         }
 
         // Update parent back pontairos that can have a new root
-        if *self == bk_ptr_parent.left(version) {
-            bk_ptr_parent.set_left(new_node_ptr, version);
-        } else if *self == bk_ptr_parent.right(version) {
-            bk_ptr_parent.set_right(new_node_ptr, version);
+        if self.same(ptr, self.left(bk_ptr_parent, version), version) {
+            self.set_left(bk_ptr_parent, new_node_ptr, version);
+        } else if self.same(ptr, self.right(bk_ptr_parent, version), version) {
+            self.set_right(bk_ptr_parent, new_node_ptr, version);
         };
     }
 
-    fn set_parent(&mut self, parent: NodePtr<K, V>, version: usize) {
-        let mut ptr = self.get_last_copy(version);
-        unsafe {
-            let new_mod = ModData::Parent(parent);
-            ptr.set_modification(new_mod, version);
-        }
+    fn set_parent(&mut self, ptr: NodePtr, parent: NodePtr, version: usize) {
+        let ptr = self.get_last_copy(ptr, version);
+        self.set_modification(ptr, ModData::Parent(parent), version);
+    }
+
+    fn set_left(&mut self, ptr: NodePtr, left: NodePtr, version: usize) {
+        let ptr = self.get_last_copy(ptr, version);
+        self.set_modification(ptr, ModData::Left(left), version);
+    }
+
+    fn set_right(&mut self, ptr: NodePtr, right: NodePtr, version: usize) {
+        let ptr = self.get_last_copy(ptr, version);
+        self.set_modification(ptr, ModData::Right(right), version);
     }
 
-    fn set_left(&mut self, left: NodePtr<K, V>, version: usize) {
-        let mut ptr = self.get_last_copy(version);
-        unsafe {
-            let new_mod = ModData::Left(left);
-            ptr.set_modification(new_mod, version);
+    fn parent(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        if ptr.is_null() {
+            return ptr;
+        }
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.parent;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Parent(d) = m.data {
+                value = d;
+            }
         }
+        self.get_last_copy(value, version)
     }
 
-    fn set_right(&mut self, right: NodePtr<K, V>, version: usize) {
-        let mut ptr = self.get_last_copy(version);
-        unsafe {
-            let new_mod = ModData::Right(right);
-            ptr.set_modification(new_mod, version);
+    fn left(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        if ptr.is_null() {
+            return ptr;
+        }
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.left;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Left(d) = m.data {
+                value = d;
+            }
         }
+        self.get_last_copy(value, version)
     }
 
-    fn parent(&self, version: usize) -> NodePtr<K, V> {
-        if self.is_null() {
-            unsafe {
-                return (*self.pointer).parent;
+    fn right(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        if ptr.is_null() {
+            return ptr;
+        }
+        let node = self.node(self.get_last_copy(ptr, version));
+        let mut value = node.right;
+        for m in node.mods.iter() {
+            if !self.sees(m.version, version) {
+                break;
+            }
+            if let ModData::Right(d) = m.data {
+                value = d;
             }
         }
-        unsafe {
-            let ptr = self.get_last_copy(version);
-            let mut value = (*ptr.pointer).parent;
-            for m in (*ptr.pointer).mods.iter() {
-                if m.version > version {
-                    break;
+        self.get_last_copy(value, version)
+    }
+
+    fn next(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        if !self.right(ptr, version).is_null() {
+            self.min_node(self.right(ptr, version), version)
+        } else {
+            let mut temp = ptr;
+            loop {
+                if self.parent(temp, version).is_null() {
+                    return NodePtr::null();
                 }
-                if let ModData::Parent(d) = m.data {
-                    value = d;
+                if self.is_left_child(temp, version) {
+                    return self.parent(temp, version);
                 }
+                temp = self.parent(temp, version);
             }
-            value.get_last_copy(version)
         }
     }
 
-    fn left(&self, version: usize) -> NodePtr<K, V> {
-        if self.is_null() {
-            return *self;
-        }
-        unsafe {
-            let ptr = self.get_last_copy(version);
-            let mut value = (*ptr.pointer).left;
-            for m in (*ptr.pointer).mods.iter() {
-                if m.version > version {
-                    break;
+    fn prev(&self, ptr: NodePtr, version: usize) -> NodePtr {
+        if !self.left(ptr, version).is_null() {
+            self.max_node(self.left(ptr, version), version)
+        } else {
+            let mut temp = ptr;
+            loop {
+                if self.parent(temp, version).is_null() {
+                    return NodePtr::null();
                 }
-                if let ModData::Left(d) = m.data {
-                    value = d;
+                if self.is_right_child(temp, version) {
+                    return self.parent(temp, version);
                 }
+                temp = self.parent(temp, version);
             }
-            value.get_last_copy(version)
         }
     }
 
-    fn right(&self, version: usize) -> NodePtr<K, V> {
-        if self.is_null() {
-            return *self;
+    /// Copies the subtree of `ptr` as seen from `version` into `other`,
+    /// without any mods.
+    fn deep_clone(
+        &self,
+        ptr: NodePtr,
+        version: usize,
+        other: &mut Arena<K, V, MODS, M>,
+    ) -> NodePtr {
+        let mut node = GojoNode::new(self.key(ptr).clone(), self.value_at(ptr, version).clone());
+        node.color = self.get_color(ptr, version);
+        node.size = self.size(ptr, version);
+        node.count = self.count(ptr, version);
+        node.agg = self.agg(ptr, version);
+        let node = other.alloc(node);
+        let left = self.left(ptr, version);
+        if !left.is_null() {
+            let left = self.deep_clone(left, version, other);
+            other.node_mut(node).left = left;
+            other.node_mut(left).parent = node;
         }
-        unsafe {
-            let ptr = self.get_last_copy(version);
-            let mut value = (*ptr.pointer).right;
-            for m in (*ptr.pointer).mods.iter() {
-                if m.version > version {
-                    break;
-                }
-                if let ModData::Right(d) = m.data {
-                    value = d;
+        let right = self.right(ptr, version);
+        if !right.is_null() {
+            let right = self.deep_clone(right, version, other);
+            other.node_mut(node).right = right;
+            other.node_mut(right).parent = node;
+        }
+        node
+    }
+
+    /// Checks the copies that own `version`: their mods stay within
+    /// `MODS` and in version order, and along each chain they start in the
+    /// order they were made, so the last one is the right one.
+    fn validate_copies(&self, version: usize) -> Result<(), String> {
+        let mut is_copy = vec![false; self.nodes.len()];
+        for node in self.nodes.iter() {
+            if !node.next_copy.is_null() {
+                is_copy[node.next_copy.index()] = true;
+            }
+        }
+        for head in (0..self.nodes.len()).filter(|index| !is_copy[*index]) {
+            let mut start = None;
+            let mut caba = NodePtr(head as u32);
+            while !caba.is_null() {
+                let node = self.node(caba);
+                if self.covers(caba, version) {
+                    if node.mods.len() > MODS {
+                        return Err(format!("node {caba:?}: {} mods", node.mods.len()));
+                    }
+                    let unordered = node.mods.windows(2).any(|pair| {
+                        self.versions.cmp(pair[0].version, pair[1].version) == Ordering::Greater
+                    });
+                    if unordered {
+                        return Err(format!("node {caba:?}: mods out of version order"));
+                    }
+                    if start.is_some_and(|start| {
+                        self.versions.cmp(start, node.version) == Ordering::Greater
+                    }) {
+                        return Err(format!("node {caba:?}: starts before an older copy"));
+                    }
+                    start = Some(node.version);
                 }
+                caba = node.next_copy;
             }
-            value.get_last_copy(version)
         }
+        Ok(())
     }
 
-    fn null() -> NodePtr<K, V> {
-        NodePtr {
-            pointer: ptr::null_mut(),
-            null: true,
+    /// Checks nodes read back from somewhere else: every pointer and version
+    /// they hold is in the arena and in its version list, and chains of copies
+    /// neither merge nor loop.
+    #[cfg(feature = "serde")]
+    fn check_bounds(&self) -> Result<(), String> {
+        self.versions.check()?;
+        let in_arena = |ptr: NodePtr| ptr.is_null() || ptr.index() < self.nodes.len();
+        let mut is_copy = vec![false; self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            let ptr = NodePtr(index as u32);
+            let pointers = [
+                node.left,
+                node.right,
+                node.parent,
+                node.bk_ptr_left,
+                node.bk_ptr_right,
+                node.bk_ptr_parent,
+                node.next_copy,
+            ];
+            let mod_pointers = node.mods.iter().filter_map(|m| match m.data {
+                ModData::Parent(p) | ModData::Left(p) | ModData::Right(p) => Some(p),
+                _ => None,
+            });
+            if !pointers.into_iter().chain(mod_pointers).all(in_arena) {
+                return Err(format!("node {ptr:?}: pointer out of the arena"));
+            }
+            let versions = [Some(node.version), node.until]
+                .into_iter()
+                .flatten()
+                .chain(node.mods.iter().map(|m| m.version));
+            if !versions
+                .into_iter()
+                .all(|version| self.versions.contains(version))
+            {
+                return Err(format!("node {ptr:?}: version out of the list"));
+            }
+            if !node.next_copy.is_null() {
+                if is_copy[node.next_copy.index()] {
+                    return Err(format!("node {:?}: copy of two nodes", node.next_copy));
+                }
+                is_copy[node.next_copy.index()] = true;
+            }
+        }
+
+        // Chains that do not merge end unless they loop, and a loop has no
+        // first node to be reached from
+        let mut reached = 0;
+        for head in (0..self.nodes.len()).filter(|index| !is_copy[*index]) {
+            let mut caba = NodePtr(head as u32);
+            while !caba.is_null() {
+                reached += 1;
+                caba = self.get_next_copy(caba);
+            }
+        }
+        match reached == self.nodes.len() {
+            true => Ok(()),
+            false => Err("chain of copies in a loop".to_string()),
         }
     }
 
-    fn is_null(&self) -> bool {
-        self.null
+    // Whether every version owned by `copy` is owned by one of the `later`
+    // copies of its chain instead
+    fn shadowed(&self, copy: usize, later: &[usize], alive: &[bool]) -> bool {
+        let node = &self.nodes[copy];
+        let mut curr = Some(node.version);
+        while let Some(version) = curr {
+            if node
+                .until
+                .is_some_and(|until| self.versions.cmp(version, until) != Ordering::Less)
+            {
+                return true;
+            }
+            let cover = later
+                .iter()
+                .filter(|other| alive[**other] && self.nodes[**other].version == version)
+                .map(|other| self.nodes[*other].until)
+                .max_by(|a, b| match (a, b) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                    (Some(a), Some(b)) => self.versions.cmp(*a, *b),
+                });
+            match cover {
+                Some(until) => curr = until,
+                None => return false,
+            }
+        }
+        true
     }
 
-    fn next(self, version: usize) -> NodePtr<K, V> {
-        if !self.right(version).is_null() {
-            self.right(version).min_node(version)
-        } else {
-            let mut temp = self;
-            loop {
-                if temp.parent(version).is_null() {
-                    return NodePtr::null();
+    /// Drops every version before `keep_from`, folding their mods into the
+    /// nodes and freeing the ones no kept version reaches from `roots`.
+    /// Returns where each node was moved to, null for the freed ones.
+    fn truncate(&mut self, keep_from: usize, roots: &[NodePtr]) -> Vec<NodePtr> {
+        let first = self.versions.first();
+        let moved_to = self.versions.truncate(keep_from);
+        let kept = |version: usize| match version >= keep_from {
+            true => Some(version),
+            false => moved_to[version - first],
+        };
+
+        // A change made at a dropped version is seen by the same kept versions
+        // as if it was made at the first kept version after it
+        let versions = &self.versions;
+        let mut alive = vec![false; self.nodes.len()];
+        for (index, node) in self.nodes.iter_mut().enumerate() {
+            let until = node.until.and_then(kept);
+            let before_until = |version: usize| {
+                until.is_none_or(|until| versions.cmp(version, until) == Ordering::Less)
+            };
+            let start = match kept(node.version) {
+                Some(start) if before_until(start) => start,
+                _ => continue,
+            };
+
+            for m in mem::take(&mut node.mods) {
+                let version = match kept(m.version) {
+                    Some(version) if before_until(version) => version,
+                    _ => continue,
+                };
+                if versions.cmp(version, start) != Ordering::Greater {
+                    match m.data {
+                        ModData::Parent(p) => node.parent = p,
+                        ModData::Left(l) => node.left = l,
+                        ModData::Right(r) => node.right = r,
+                        ModData::Col(c) => node.color = c,
+                        ModData::Size(n) => node.size = n,
+                        ModData::Count(n) => node.count = n,
+                        ModData::Stamp(n) => node.stamp = n,
+                        ModData::Value(v) => node.value = v,
+                        ModData::Agg(a) => node.agg = a,
+                    }
+                    continue;
+                }
+                node.mods.retain(|old| {
+                    old.version != version
+                        || mem::discriminant(&old.data) != mem::discriminant(&m.data)
+                });
+                node.mods.push(Mod {
+                    data: m.data,
+                    version,
+                });
+            }
+            node.version = start;
+            node.until = until;
+            alive[index] = true;
+        }
+
+        let mut is_copy = vec![false; self.nodes.len()];
+        for node in self.nodes.iter() {
+            if !node.next_copy.is_null() {
+                is_copy[node.next_copy.index()] = true;
+            }
+        }
+        let mut chain_of = vec![0; self.nodes.len()];
+        for head in (0..self.nodes.len()).filter(|index| !is_copy[*index]) {
+            let mut chain = Vec::new();
+            let mut caba = NodePtr(head as u32);
+            while !caba.is_null() {
+                chain_of[caba.index()] = head;
+                chain.push(caba.index());
+                caba = self.get_next_copy(caba);
+            }
+            for (position, &copy) in chain.iter().enumerate() {
+                if alive[copy] && self.shadowed(copy, &chain[position + 1..], &alive) {
+                    alive[copy] = false;
                 }
-                if temp.is_left_child(version) {
-                    return temp.parent(version);
+            }
+        }
+
+        // Copies of a node are kept or freed together with the chain they are
+        // in, following every child pointer some kept version may read
+        let mut reached = vec![false; self.nodes.len()];
+        let mut stack: Vec<usize> = roots
+            .iter()
+            .filter(|root| !root.is_null())
+            .map(|root| chain_of[root.index()])
+            .collect();
+        while let Some(head) = stack.pop() {
+            if reached[head] {
+                continue;
+            }
+            reached[head] = true;
+            let mut caba = NodePtr(head as u32);
+            while !caba.is_null() {
+                let node = self.node(caba);
+                if alive[caba.index()] {
+                    let children = node.mods.iter().filter_map(|m| match m.data {
+                        ModData::Left(child) | ModData::Right(child) => Some(child),
+                        _ => None,
+                    });
+                    for child in [node.left, node.right].into_iter().chain(children) {
+                        if !child.is_null() {
+                            stack.push(chain_of[child.index()]);
+                        }
+                    }
                 }
-                temp = temp.parent(version);
+                caba = node.next_copy;
             }
         }
-    }
-}
 
-impl<K: Ord + Clone + Default, V: Clone + Default> NodePtr<K, V> {
-    unsafe fn deep_clone(&self, version: usize) -> NodePtr<K, V> {
-        let mut node = NodePtr::new((*self.pointer).key.clone(), (*self.pointer).value.clone());
-        if !self.left(version).is_null() {
-            node.set_left(self.left(version).deep_clone(version), version);
-            node.left(version).set_parent(node, version);
+        let mut moved = vec![NodePtr::null(); self.nodes.len()];
+        let mut next_copies = vec![NodePtr::null(); self.nodes.len()];
+        let mut amount = 0;
+        for index in 0..self.nodes.len() {
+            if alive[index] && reached[chain_of[index]] {
+                moved[index] = NodePtr(amount);
+                amount += 1;
+            }
+        }
+        for head in (0..self.nodes.len()).filter(|index| !is_copy[*index]) {
+            let mut kept_copies = Vec::new();
+            let mut caba = NodePtr(head as u32);
+            while !caba.is_null() {
+                if !moved[caba.index()].is_null() {
+                    kept_copies.push(caba);
+                }
+                caba = self.get_next_copy(caba);
+            }
+            for pair in kept_copies.windows(2) {
+                next_copies[pair[0].index()] = pair[1];
+            }
+
+            // Pointers to freed copies go to the first kept one, walking the
+            // chain from there still finds the copy that owns each version
+            let first_kept = kept_copies
+                .first()
+                .map_or(NodePtr::null(), |c| moved[c.index()]);
+            let mut caba = NodePtr(head as u32);
+            while !caba.is_null() {
+                if moved[caba.index()].is_null() {
+                    moved[caba.index()] = first_kept;
+                }
+                caba = self.get_next_copy(caba);
+            }
         }
-        if !self.right(version).is_null() {
-            node.set_right(self.right(version).deep_clone(version), version);
-            node.right(version).set_parent(node, version);
+
+        let remap = |ptr: NodePtr| match ptr.is_null() {
+            true => ptr,
+            false => moved[ptr.index()],
+        };
+        let nodes = mem::take(&mut self.nodes);
+        self.nodes = Vec::with_capacity(amount as usize);
+        for (index, mut node) in nodes.into_iter().enumerate() {
+            if !alive[index] || !reached[chain_of[index]] {
+                continue;
+            }
+            node.left = remap(node.left);
+            node.right = remap(node.right);
+            node.parent = remap(node.parent);
+            node.bk_ptr_left = remap(node.bk_ptr_left);
+            node.bk_ptr_right = remap(node.bk_ptr_right);
+            node.bk_ptr_parent = remap(node.bk_ptr_parent);
+            node.next_copy = remap(next_copies[index]);
+            for m in node.mods.iter_mut() {
+                match &mut m.data {
+                    ModData::Parent(p) | ModData::Left(p) | ModData::Right(p) => *p = remap(*p),
+                    ModData::Col(_)
+                    | ModData::Size(_)
+                    | ModData::Count(_)
+                    | ModData::Stamp(_)
+                    | ModData::Value(_)
+                    | ModData::Agg(_) => (),
+                }
+            }
+            self.nodes.push(node);
         }
-        node
+        self.index_copies();
+        moved
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct NodeInfo<K: Ord + Default + Clone + Debug, V: Default + Clone + Debug> {
+pub struct NodeInfo<K: Ord + Clone, V: Clone> {
     pub depth: usize,
-    pub node_ptr: NodePtr<K, V>,
+    pub node_ptr: NodePtr,
     pub color: Color,
     pub key: K,
     pub value: V,
@@ -582,8 +1373,8 @@ pub struct NodeInfo<K: Ord + Default + Clone + Debug, V: Default + Clone + Debug
 
 impl<K, V> NodeInfo<K, V>
 where
-    K: Ord + Clone + Default + Debug,
-    V: Clone + Default + Debug,
+    K: Ord + Clone,
+    V: Clone,
 {
     #[allow(unused)]
     pub fn new(depth: usize, key: K, value: V, color: Color) -> NodeInfo<K, V> {
@@ -597,38 +1388,45 @@ where
         }
     }
 
-    fn from_node_ptr(node_ptr: NodePtr<K, V>, depth: usize, version: usize) -> NodeInfo<K, V> {
-        unsafe {
-            let key = (*node_ptr.pointer).key.clone();
-            let value = (*node_ptr.pointer).value.clone();
-            let color = node_ptr.get_color(version);
-            Self {
-                depth,
-                node_ptr,
-                color,
-                key,
-                value,
-            }
+    fn from_node_ptr<const MODS: usize, M: Monoid<K, V>>(
+        arena: &Arena<K, V, MODS, M>,
+        node_ptr: NodePtr,
+        depth: usize,
+        version: usize,
+    ) -> NodeInfo<K, V> {
+        let key = arena.key(node_ptr).clone();
+        let value = arena.value_at(node_ptr, version).clone();
+        let color = arena.get_color(node_ptr, version);
+        Self {
+            depth,
+            node_ptr,
+            color,
+            key,
+            value,
         }
     }
 
-    fn successor(&mut self, version: usize) -> Option<NodeInfo<K, V>> {
+    fn successor<const MODS: usize, M: Monoid<K, V>>(
+        &mut self,
+        arena: &Arena<K, V, MODS, M>,
+        version: usize,
+    ) -> Option<NodeInfo<K, V>> {
         let mut depth = self.depth;
         let mut x = self.node_ptr;
-        if !x.right(version).is_null() {
-            x = x.right(version);
+        if !arena.right(x, version).is_null() {
+            x = arena.right(x, version);
             depth += 1;
-            while !x.left(version).is_null() {
-                x = x.left(version);
+            while !arena.left(x, version).is_null() {
+                x = arena.left(x, version);
                 depth += 1;
             }
-            return Some(NodeInfo::from_node_ptr(x, depth, version));
+            return Some(NodeInfo::from_node_ptr(arena, x, depth, version));
         }
 
-        let mut y = x.parent(version);
-        while !y.is_null() && x == y.right(version) {
+        let mut y = arena.parent(x, version);
+        while !y.is_null() && arena.same(x, arena.right(y, version), version) {
             x = y;
-            y = x.parent(version);
+            y = arena.parent(x, version);
             depth -= 1;
         }
 
@@ -637,70 +1435,128 @@ where
         }
 
         depth -= 1;
-        Some(NodeInfo::from_node_ptr(y, depth, version))
+        Some(NodeInfo::from_node_ptr(arena, y, depth, version))
     }
 
-    fn first_child(root: NodePtr<K, V>, version: usize) -> NodeInfo<K, V> {
+    fn predecessor<const MODS: usize, M: Monoid<K, V>>(
+        &mut self,
+        arena: &Arena<K, V, MODS, M>,
+        version: usize,
+    ) -> Option<NodeInfo<K, V>> {
+        let mut depth = self.depth;
+        let mut x = self.node_ptr;
+        if !arena.left(x, version).is_null() {
+            x = arena.left(x, version);
+            depth += 1;
+            while !arena.right(x, version).is_null() {
+                x = arena.right(x, version);
+                depth += 1;
+            }
+            return Some(NodeInfo::from_node_ptr(arena, x, depth, version));
+        }
+
+        let mut y = arena.parent(x, version);
+        while !y.is_null() && arena.same(x, arena.left(y, version), version) {
+            x = y;
+            y = arena.parent(x, version);
+            depth -= 1;
+        }
+
+        if y.is_null() {
+            return None;
+        }
+
+        depth -= 1;
+        Some(NodeInfo::from_node_ptr(arena, y, depth, version))
+    }
+
+    fn first_child<const MODS: usize, M: Monoid<K, V>>(
+        arena: &Arena<K, V, MODS, M>,
+        root: NodePtr,
+        version: usize,
+    ) -> Option<NodeInfo<K, V>> {
         let mut depth = 0;
         if root.is_null() {
-            return NodeInfo::from_node_ptr(NodePtr::null(), depth, version);
+            return None;
         }
         let mut temp = root;
-        while !temp.left(version).is_null() {
-            temp = temp.left(version);
+        while !arena.left(temp, version).is_null() {
+            temp = arena.left(temp, version);
             depth += 1;
         }
-        NodeInfo::from_node_ptr(temp, depth, version)
+        Some(NodeInfo::from_node_ptr(arena, temp, depth, version))
     }
 
-    fn last_child(root: NodePtr<K, V>, version: usize) -> NodeInfo<K, V> {
+    fn last_child<const MODS: usize, M: Monoid<K, V>>(
+        arena: &Arena<K, V, MODS, M>,
+        root: NodePtr,
+        version: usize,
+    ) -> Option<NodeInfo<K, V>> {
         let mut depth = 0;
         if root.is_null() {
-            return NodeInfo::from_node_ptr(NodePtr::null(), depth, version);
+            return None;
         }
 
         let mut temp = root;
-        while !temp.right(version).is_null() {
-            temp = temp.right(version);
+        while !arena.right(temp, version).is_null() {
+            temp = arena.right(temp, version);
             depth += 1;
         }
-        NodeInfo::from_node_ptr(temp, depth, version)
+        Some(NodeInfo::from_node_ptr(arena, temp, depth, version))
     }
 
+    #[allow(dead_code)]
     fn is_null(&self) -> bool {
         self.node_ptr.is_null()
     }
 
-    fn next(&mut self, version: usize) -> Option<NodeInfo<K, V>> {
-        self.successor(version)
+    fn next<const MODS: usize, M: Monoid<K, V>>(
+        &mut self,
+        arena: &Arena<K, V, MODS, M>,
+        version: usize,
+    ) -> Option<NodeInfo<K, V>> {
+        self.successor(arena, version)
+    }
+
+    fn prev<const MODS: usize, M: Monoid<K, V>>(
+        &mut self,
+        arena: &Arena<K, V, MODS, M>,
+        version: usize,
+    ) -> Option<NodeInfo<K, V>> {
+        self.predecessor(arena, version)
     }
 }
 
-pub struct NodeInfoIter<'a, K: Ord + Default + Clone + Debug + 'a, V: Default + Clone + Debug + 'a>
-{
+pub struct NodeInfoIter<
+    'a,
+    K: Ord + Clone + 'a,
+    V: Clone + 'a,
+    const MODS: usize = 6,
+    M: Monoid<K, V> = (),
+> {
+    arena: &'a Arena<K, V, MODS, M>,
     head: Option<NodeInfo<K, V>>,
     tail: Option<NodeInfo<K, V>>,
     len: usize,
     version: usize,
-    _marker: marker::PhantomData<&'a ()>,
 }
 
-impl<'a, K: Ord + Clone + Default + Debug + 'a, V: Default + Clone + Debug + 'a> Clone
-    for NodeInfoIter<'a, K, V>
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> Clone
+    for NodeInfoIter<'a, K, V, MODS, M>
 {
-    fn clone(&self) -> NodeInfoIter<'a, K, V> {
+    fn clone(&self) -> NodeInfoIter<'a, K, V, MODS, M> {
         NodeInfoIter {
+            arena: self.arena,
             head: self.head.clone(),
             tail: self.tail.clone(),
             len: self.len,
             version: self.version,
-            _marker: self._marker,
         }
     }
 }
 
-impl<'a, K: Ord + Default + Clone + Debug + 'a, V: Default + Clone + Debug + 'a> Iterator
-    for NodeInfoIter<'a, K, V>
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> Iterator
+    for NodeInfoIter<'a, K, V, MODS, M>
 {
     type Item = NodeInfo<K, V>;
 
@@ -713,7 +1569,7 @@ impl<'a, K: Ord + Default + Clone + Debug + 'a, V: Default + Clone + Debug + 'a>
 
         if let Some(value) = &self.head {
             let mut info = value.clone();
-            self.head = info.next(self.version);
+            self.head = info.next(self.arena, self.version);
             self.len -= 1;
             return Some(info);
         }
@@ -726,27 +1582,66 @@ impl<'a, K: Ord + Default + Clone + Debug + 'a, V: Default + Clone + Debug + 'a>
     }
 }
 
-pub struct GojoIter<'a, K: Ord + Default + Clone + 'a, V: Default + Clone + 'a> {
-    head: NodePtr<K, V>,
-    tail: NodePtr<K, V>,
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> DoubleEndedIterator
+    for NodeInfoIter<'a, K, V, MODS, M>
+{
+    fn next_back(&mut self) -> Option<NodeInfo<K, V>> {
+        if self.len == 0 {
+            return None;
+        }
+
+        if let Some(value) = &self.tail {
+            let mut info = value.clone();
+            self.tail = info.prev(self.arena, self.version);
+            self.len -= 1;
+            return Some(info);
+        }
+
+        None
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> ExactSizeIterator
+    for NodeInfoIter<'a, K, V, MODS, M>
+{
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> FusedIterator
+    for NodeInfoIter<'a, K, V, MODS, M>
+{
+}
+
+pub struct GojoIter<
+    'a,
+    K: Ord + Clone + 'a,
+    V: Clone + 'a,
+    const MODS: usize = 6,
+    M: Monoid<K, V> = (),
+> {
+    arena: &'a Arena<K, V, MODS, M>,
+    head: NodePtr,
+    tail: NodePtr,
     len: usize,
     version: usize,
-    _marker: marker::PhantomData<&'a ()>,
 }
 
-impl<'a, K: Ord + Clone + Default + 'a, V: Default + Clone + 'a> Clone for GojoIter<'a, K, V> {
-    fn clone(&self) -> GojoIter<'a, K, V> {
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> Clone
+    for GojoIter<'a, K, V, MODS, M>
+{
+    fn clone(&self) -> GojoIter<'a, K, V, MODS, M> {
         GojoIter {
+            arena: self.arena,
             head: self.head,
             tail: self.tail,
             len: self.len,
             version: self.version,
-            _marker: self._marker,
         }
     }
 }
 
-impl<'a, K: Ord + Default + Clone + 'a, V: Default + Clone + 'a> Iterator for GojoIter<'a, K, V> {
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> Iterator
+    for GojoIter<'a, K, V, MODS, M>
+{
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<(&'a K, &'a V)> {
@@ -758,8 +1653,12 @@ impl<'a, K: Ord + Default + Clone + 'a, V: Default + Clone + 'a> Iterator for Go
             return None;
         }
 
-        let (k, v) = unsafe { (&(*self.head.pointer).key, &(*self.head.pointer).value) };
-        self.head = self.head.next(self.version);
+        let arena = self.arena;
+        let (k, v) = (
+            arena.key(self.head),
+            arena.value_at(self.head, self.version),
+        );
+        self.head = arena.next(self.head, self.version);
         self.len -= 1;
         Some((k, v))
     }
@@ -769,90 +1668,479 @@ impl<'a, K: Ord + Default + Clone + 'a, V: Default + Clone + 'a> Iterator for Go
     }
 }
 
-#[derive(Debug)]
-pub struct Gojo<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> {
-    root: NodePtr<K, V>,
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> DoubleEndedIterator
+    for GojoIter<'a, K, V, MODS, M>
+{
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        if self.tail.is_null() {
+            return None;
+        }
+
+        let arena = self.arena;
+        let (k, v) = (
+            arena.key(self.tail),
+            arena.value_at(self.tail, self.version),
+        );
+        self.tail = arena.prev(self.tail, self.version);
+        self.len -= 1;
+        Some((k, v))
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> ExactSizeIterator
+    for GojoIter<'a, K, V, MODS, M>
+{
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> FusedIterator
+    for GojoIter<'a, K, V, MODS, M>
+{
+}
+
+pub struct GojoRange<
+    'a,
+    K: Ord + Clone + 'a,
+    V: Clone + 'a,
+    const MODS: usize = 6,
+    M: Monoid<K, V> = (),
+> {
+    arena: &'a Arena<K, V, MODS, M>,
+    head: NodePtr,
+    end: Bound<K>,
+    version: usize,
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> Clone
+    for GojoRange<'a, K, V, MODS, M>
+{
+    fn clone(&self) -> GojoRange<'a, K, V, MODS, M> {
+        GojoRange {
+            arena: self.arena,
+            head: self.head,
+            end: self.end.clone(),
+            version: self.version,
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>> Iterator
+    for GojoRange<'a, K, V, MODS, M>
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.head.is_null() {
+            return None;
+        }
+
+        let arena = self.arena;
+        let (k, v) = (
+            arena.key(self.head),
+            arena.value_at(self.head, self.version),
+        );
+        let before_end = match &self.end {
+            Bound::Included(end) => k <= end,
+            Bound::Excluded(end) => k < end,
+            Bound::Unbounded => true,
+        };
+        if !before_end {
+            self.head = NodePtr::null();
+            return None;
+        }
+
+        self.head = arena.next(self.head, self.version);
+        Some((k, v))
+    }
+}
+
+/// A key that differs between two versions of a `Gojo`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffEvent<'a, K, V> {
+    /// Only in the new version.
+    Added(&'a K, &'a V),
+    /// Only in the old version.
+    Removed(&'a K, &'a V),
+    /// In both versions, with another value or another count of copies.
+    Changed { key: &'a K, old: &'a V, new: &'a V },
+}
+
+#[derive(Clone, Copy)]
+enum Pending {
+    Subtree(NodePtr),
+    Node(NodePtr),
+}
+
+/// Walks two versions side by side in key order, skipping every subtree both
+/// of them share, so it costs as much as what changed between them.
+pub struct GojoDiff<
+    'a,
+    K: Ord + Clone + 'a,
+    V: Clone + 'a,
+    const MODS: usize = 6,
+    M: Monoid<K, V> = (),
+> {
+    arena: &'a Arena<K, V, MODS, M>,
+    old: Vec<Pending>,
+    new: Vec<Pending>,
+    old_version: usize,
+    new_version: usize,
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + 'a, const MODS: usize, M: Monoid<K, V>>
+    GojoDiff<'a, K, V, MODS, M>
+{
+    // Replaces the subtree on top of `stack` by its left subtree, its root and
+    // its right subtree
+    fn expand(arena: &Arena<K, V, MODS, M>, stack: &mut Vec<Pending>, version: usize) {
+        if let Some(Pending::Subtree(x)) = stack.pop() {
+            let right = arena.right(x, version);
+            if !right.is_null() {
+                stack.push(Pending::Subtree(right));
+            }
+            stack.push(Pending::Node(x));
+            let left = arena.left(x, version);
+            if !left.is_null() {
+                stack.push(Pending::Subtree(left));
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + PartialEq + 'a, const MODS: usize, M: Monoid<K, V>>
+    GojoDiff<'a, K, V, MODS, M>
+{
+    // Takes one pending subtree or node off the stacks, `None` once both
+    // versions are exhausted
+    fn step(&mut self) -> Option<Option<DiffEvent<'a, K, V>>> {
+        let arena = self.arena;
+        let (old_version, new_version) = (self.old_version, self.new_version);
+        let event = match (self.old.last().copied(), self.new.last().copied()) {
+            (None, None) => return None,
+            (Some(Pending::Subtree(a)), Some(Pending::Subtree(b)))
+                if a == b && arena.stamp(a, old_version) == arena.stamp(b, new_version) =>
+            {
+                self.old.pop();
+                self.new.pop();
+                None
+            }
+            // The smaller subtree may be shared with some subtree of the
+            // bigger one
+            (Some(Pending::Subtree(a)), Some(Pending::Subtree(b))) => {
+                match arena.size(a, old_version) >= arena.size(b, new_version) {
+                    true => Self::expand(arena, &mut self.old, old_version),
+                    false => Self::expand(arena, &mut self.new, new_version),
+                }
+                None
+            }
+            (Some(Pending::Subtree(_)), _) => {
+                Self::expand(arena, &mut self.old, old_version);
+                None
+            }
+            (_, Some(Pending::Subtree(_))) => {
+                Self::expand(arena, &mut self.new, new_version);
+                None
+            }
+            (Some(Pending::Node(a)), Some(Pending::Node(b))) => {
+                match arena.key(a).cmp(arena.key(b)) {
+                    Ordering::Less => {
+                        self.old.pop();
+                        Some(DiffEvent::Removed(
+                            arena.key(a),
+                            arena.value_at(a, old_version),
+                        ))
+                    }
+                    Ordering::Greater => {
+                        self.new.pop();
+                        Some(DiffEvent::Added(
+                            arena.key(b),
+                            arena.value_at(b, new_version),
+                        ))
+                    }
+                    Ordering::Equal => {
+                        self.old.pop();
+                        self.new.pop();
+                        let old = arena.value_at(a, old_version);
+                        let new = arena.value_at(b, new_version);
+                        match old != new
+                            || arena.count(a, old_version) != arena.count(b, new_version)
+                        {
+                            true => Some(DiffEvent::Changed {
+                                key: arena.key(b),
+                                old,
+                                new,
+                            }),
+                            false => None,
+                        }
+                    }
+                }
+            }
+            (Some(Pending::Node(a)), None) => {
+                self.old.pop();
+                Some(DiffEvent::Removed(
+                    arena.key(a),
+                    arena.value_at(a, old_version),
+                ))
+            }
+            (None, Some(Pending::Node(b))) => {
+                self.new.pop();
+                Some(DiffEvent::Added(
+                    arena.key(b),
+                    arena.value_at(b, new_version),
+                ))
+            }
+        };
+        Some(event)
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + PartialEq + 'a, const MODS: usize, M: Monoid<K, V>>
+    Iterator for GojoDiff<'a, K, V, MODS, M>
+{
+    type Item = DiffEvent<'a, K, V>;
+
+    fn next(&mut self) -> Option<DiffEvent<'a, K, V>> {
+        loop {
+            if let Some(event) = self.step()? {
+                return Some(event);
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, V: Clone + PartialEq + 'a, const MODS: usize, M: Monoid<K, V>>
+    FusedIterator for GojoDiff<'a, K, V, MODS, M>
+{
+}
+
+/// Room taken by the arena of a `Gojo`, over every version.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArenaStats {
+    /// Nodes in the arena, copies included.
+    pub nodes: usize,
+    /// Nodes made by copying a fat node with no room left for mods.
+    pub copies: usize,
+    /// Bytes allocated for nodes, without the heap data of keys and values.
+    pub bytes: usize,
+}
+
+/// Every fat node holds up to `MODS` changes before it is copied.
+///
+/// Cloning copies the whole history, every version of the clone can be
+/// queried and derived from as in the original.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "K: Serialize, V: Serialize, M::Summary: Serialize",
+        deserialize = "K: Deserialize<'de>, V: Deserialize<'de>, M::Summary: Deserialize<'de>"
+    ))
+)]
+pub struct Gojo<K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()> {
+    root: NodePtr,
     len: usize,
     curr_version: usize,
-    roots: Vec<(NodePtr<K, V>, usize)>,
-    nil: NodePtr<K, V>,
+    first_version: usize,
+    roots: Vec<(NodePtr, usize, Option<usize>)>,
+    arena: Arena<K, V, MODS, M>,
+    policy: DuplicatePolicy,
+    labels: BTreeMap<String, usize>,
+    // Sorted by version and by timestamp alike
+    timestamps: Vec<(usize, u64)>,
 }
 
-impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Default for Gojo<K, V> {
+impl<K: Ord + Clone, V: Clone> Default for Gojo<K, V> {
     fn default() -> Self {
         Self::new(DEFAULT_MAX_OPS)
     }
 }
 
-unsafe impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Send for Gojo<K, V> {}
-
-unsafe impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Sync for Gojo<K, V> {}
+/// Sorts the pairs and builds the tree in a single version, see
+/// `Gojo::from_sorted_iter`. Of equal keys the last value is kept, as in a
+/// `BTreeMap`, and the tree gets the default duplicate policy for later
+/// inserts.
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> FromIterator<(K, V)>
+    for Gojo<K, V, MODS, M>
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut pairs: Vec<(K, V)> = iter.into_iter().collect();
+        pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        // The sort is stable, the later pair of equal keys takes the place of
+        // the one kept before it
+        pairs.dedup_by(|later, kept| {
+            let same = later.0 == kept.0;
+            if same {
+                mem::swap(later, kept);
+            }
+            same
+        });
+        Self::from_sorted_iter(pairs, DuplicatePolicy::default())
+            .expect("the pairs were just sorted")
+    }
+}
 
-// Drop all owned pointers if the tree is dropped
-impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Drop for Gojo<K, V> {
-    fn drop(&mut self) {
-        self.clear();
+/// Inserts every pair in a single new version derived from the latest one, as
+/// `Gojo::batch` does. No version is created when there are no pairs.
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Extend<(K, V)>
+    for Gojo<K, V, MODS, M>
+{
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let mut pairs = iter.into_iter().peekable();
+        if pairs.peek().is_none() {
+            return;
+        }
+        self.batch(|tx| {
+            pairs.for_each(|(k, v)| tx.insert(k, v));
+            Ok(())
+        })
+        .expect("a batch of inserts on the latest version can not fail");
     }
 }
 
-/// If key and value are both impl Clone, we can call clone to get a copy.
-impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Clone for Gojo<K, V> {
-    fn clone(&self) -> Gojo<K, V> {
-        unsafe {
-            let amount_of_ops = self.roots.len();
-            let mut new = Gojo::new(amount_of_ops);
-            new.root = self.root.deep_clone(self.curr_version);
-            new.len = self.len;
-            new
+#[cfg(feature = "serde")]
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M> {
+    /// Writes the tree with every version of its history to `writer`, in a
+    /// compact binary form.
+    pub fn write_to(&self, writer: impl Write) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+        M::Summary: Serialize,
+    {
+        bincode::serialize_into(writer, self)?;
+        Ok(())
+    }
+
+    /// Reads back a tree written with `write_to`, with all its versions.
+    /// Every version is validated, `GojoError::BrokenInvariant` is returned
+    /// for one that is not a valid tree.
+    pub fn read_from(reader: impl Read) -> Result<Gojo<K, V, MODS, M>>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+        M::Summary: DeserializeOwned,
+    {
+        let mut gojo: Gojo<K, V, MODS, M> = bincode::deserialize_from(reader)?;
+        gojo.check_bounds().map_err(GojoError::BrokenInvariant)?;
+        gojo.arena.index_copies();
+        gojo.validate_all()?;
+        Ok(gojo)
+    }
+
+    // Checks the versions and roots read back point inside the arena, before
+    // anything walks it
+    fn check_bounds(&self) -> Result<(), String> {
+        self.arena.check_bounds()?;
+        let versions = &self.arena.versions;
+        if self.first_version > self.curr_version
+            || versions.first() != self.first_version
+            || !versions.contains(self.curr_version)
+            || self.roots.len() != self.curr_version - self.first_version + 1
+        {
+            return Err("versions out of the list".to_string());
+        }
+        let in_arena = |ptr: NodePtr| ptr.is_null() || ptr.index() < self.arena.nodes.len();
+        match in_arena(self.root) && self.roots.iter().all(|(root, _, _)| in_arena(*root)) {
+            true => Ok(()),
+            false => Err("root out of the arena".to_string()),
         }
     }
 }
 
 #[allow(dead_code)]
-impl<K, V> Gojo<K, V>
+impl<K, V, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M>
 where
-    K: Ord + Clone + Default + Debug,
-    V: Clone + Default + Debug,
+    K: Ord + Clone + Debug,
+    V: Clone + Debug,
 {
-    pub fn print_in_order(node: NodePtr<K, V>, version: usize) {
+    pub fn print_in_order(&self, node: NodePtr, version: usize) {
         if node.is_null() {
             return;
         }
-        Self::print_in_order(node.left(version), version);
-        println!("{:?}", node.get_last_copy(version));
-        Self::print_in_order(node.right(version), version);
+        self.print_in_order(self.arena.left(node, version), version);
+        println!(
+            "{:?}",
+            self.arena.node(self.arena.get_last_copy(node, version))
+        );
+        self.print_in_order(self.arena.right(node, version), version);
     }
 }
 
-impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Gojo<K, V> {
+impl<K: Ord + Clone, V: Clone> Gojo<K, V> {
     /// Creates an empty `RBTree`.
     pub fn new(predected_amount_of_ops: usize) -> Gojo<K, V> {
-        let mut nil = NodePtr::new(K::default(), V::default());
-        nil.null = true;
-        unsafe {
-            (*(nil.pointer)).color = Color::Black;
-            (*(nil.pointer)).left = nil;
-            (*(nil.pointer)).right = nil;
-            (*(nil.pointer)).parent = nil;
-        }
+        Self::with_policy(predected_amount_of_ops, DuplicatePolicy::default())
+    }
+
+    /// Creates an empty `RBTree` that handles keys inserted again as `policy`
+    /// says.
+    pub fn with_policy(predected_amount_of_ops: usize, policy: DuplicatePolicy) -> Gojo<K, V> {
+        Self::with_mods(predected_amount_of_ops, policy)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Gojo<K, V, MODS, M> {
+    /// Creates an empty `RBTree` whose fat nodes hold up to `MODS` changes
+    /// before being copied. More mods mean fewer copies but bigger nodes.
+    /// `MODS` must be at least 2, a change hidden from later versions takes
+    /// two of them at once.
+    pub fn with_mods(
+        predected_amount_of_ops: usize,
+        policy: DuplicatePolicy,
+    ) -> Gojo<K, V, MODS, M> {
+        const { assert!(MODS >= 2, "a gojo fat node needs room for at least 2 mods") };
         let mut roots = Vec::with_capacity(predected_amount_of_ops);
-        roots.push((nil, 0));
+        roots.push((NodePtr::null(), 0, None));
         Gojo {
             root: NodePtr::null(),
             len: 0,
             curr_version: 0,
+            first_version: 0,
             roots,
-            nil,
+            arena: Arena::new(VersionList::new(predected_amount_of_ops)),
+            policy,
+            labels: BTreeMap::new(),
+            timestamps: Vec::new(),
         }
     }
 
-    /// Returns the len of `RBTree`.
-    pub fn len(&self, version: usize) -> Option<usize> {
+    /// Builds a perfectly balanced tree holding every pair of `iter` as
+    /// version 1, in linear time. Keys must come in order, equal ones are
+    /// handled as `policy` says, which the tree keeps for later inserts.
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(
+        iter: I,
+        policy: DuplicatePolicy,
+    ) -> Result<Gojo<K, V, MODS, M>> {
+        let iter = iter.into_iter();
+        let mut gojo = Self::with_mods(DEFAULT_MAX_OPS, policy);
+        gojo.arena.nodes.reserve(iter.size_hint().0);
+        gojo.build_sorted(iter)?;
+        Ok(gojo)
+    }
+
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.policy
+    }
+
+    // Entry of `roots` for `version`, that must be neither unknown nor pruned
+    fn root_entry(&self, version: usize) -> Result<(NodePtr, usize, Option<usize>)> {
         if version > self.latest_version() {
-            return None;
+            anyhow::bail!(GojoError::UnknownVersion(format!("{version}")));
+        }
+        if version < self.first_version {
+            anyhow::bail!(GojoError::VersionPruned(format!("{version}")));
         }
 
-        let (_, length) = self.roots[version];
+        Ok(self.roots[version - self.first_version])
+    }
+
+    /// Returns the len of `RBTree`.
+    pub fn len(&self, version: usize) -> Option<usize> {
+        let (_, length, _) = self.root_entry(version).ok()?;
         Some(length)
     }
 
@@ -860,276 +2148,622 @@ impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Gojo<K, V> {
         self.curr_version
     }
 
-    pub fn get_root(&self, version: usize) -> NodePtr<K, V> {
-        if version > self.latest_version() {
-            return NodePtr::null();
+    /// Returns the version `version` was derived from, `None` for version 0.
+    pub fn parent_version(&self, version: usize) -> Option<usize> {
+        self.root_entry(version).ok()?.2
+    }
+
+    /// Names `version` with `label`, that no other version can have.
+    pub fn label_version(&mut self, version: usize, label: &str) -> Result<()> {
+        self.root_entry(version)?;
+        match self.labels.get(label) {
+            Some(labeled) if *labeled != version => {
+                anyhow::bail!(GojoError::LabelInUse(label.to_string()));
+            }
+            Some(_) => (),
+            None => {
+                self.labels.insert(label.to_string(), version);
+            }
         }
+        Ok(())
+    }
 
-        self.roots[version].0
+    pub fn version_by_label(&self, label: &str) -> Option<usize> {
+        self.labels.get(label).copied()
     }
 
-    pub fn is_empty(&self, version: usize) -> bool {
-        if version > self.latest_version() {
-            return true;
+    /// Marks `version` with `timestamp`. Timestamps must grow with versions,
+    /// a later version can not have an earlier one.
+    pub fn stamp_version(&mut self, version: usize, timestamp: u64) -> Result<()> {
+        self.root_entry(version)?;
+        let at = self.timestamps.partition_point(|(v, _)| *v < version);
+        let stamped = self.timestamps.get(at).is_some_and(|(v, _)| *v == version);
+        let before = at.checked_sub(1).map(|i| self.timestamps[i].1);
+        let after = self.timestamps.get(at + stamped as usize).map(|(_, t)| *t);
+        if before.is_some_and(|t| t > timestamp) || after.is_some_and(|t| t < timestamp) {
+            anyhow::bail!(GojoError::TimestampOutOfOrder(format!("{timestamp}")));
         }
-        let len = self.roots[version].1;
-        len == 0
-    }
 
-    pub fn predecessor(&self, k: &K, version: usize) -> Option<&V> {
-        let node = self.find_node(k, version);
-        if node.is_null() {
-            return None;
+        match stamped {
+            true => self.timestamps[at].1 = timestamp,
+            false => self.timestamps.insert(at, (version, timestamp)),
         }
+        Ok(())
+    }
 
-        let succ = self.predecessor_helper(node, version);
-        if succ.is_null() {
-            return None;
+    pub fn timestamp(&self, version: usize) -> Option<u64> {
+        let at = self.timestamps.partition_point(|(v, _)| *v < version);
+        match self.timestamps.get(at) {
+            Some((v, t)) if *v == version => Some(*t),
+            _ => None,
         }
+    }
 
-        unsafe { Some(&(*succ.pointer).value) }
+    /// Latest stamped version whose timestamp is not after `timestamp`.
+    pub fn version_at_or_before(&self, timestamp: u64) -> Option<usize> {
+        let at = self.timestamps.partition_point(|(_, t)| *t <= timestamp);
+        Some(self.timestamps[at.checked_sub(1)?].0)
     }
 
-    fn predecessor_helper(&self, node: NodePtr<K, V>, version: usize) -> NodePtr<K, V> {
-        let mut x = node;
-        if !x.left(version).is_null() {
-            return x.left(version).max_node(version);
+    pub fn get_root(&self, version: usize) -> NodePtr {
+        match self.root_entry(version) {
+            Ok((root, _, _)) => root,
+            Err(_) => NodePtr::null(),
         }
+    }
 
-        let mut y = x.parent(version);
-        while !y.is_null() && x.is_left_child(version) {
-            x = y;
-            y = x.parent(version);
+    pub fn is_empty(&self, version: usize) -> bool {
+        match self.root_entry(version) {
+            Ok((_, len, _)) => len == 0,
+            Err(_) => true,
         }
-        y
     }
 
+    /// Value of the greatest key at `version` that is less than `k`, that
+    /// does not need to be there, as `strict_predecessor` finds it. `floor`
+    /// is the one that takes `k` itself too.
+    pub fn predecessor(&self, k: &K, version: usize) -> Option<&V> {
+        self.strict_predecessor(k, version).map(|(_, v)| v)
+    }
+
+    /// Value of the smallest key at `version` that is greater than `k`, that
+    /// does not need to be there, as `strict_successor` finds it. `ceiling`
+    /// is the one that takes `k` itself too.
     pub fn successor_by_key(&self, k: &K, version: usize) -> Option<&V> {
-        if version > self.latest_version() {
+        let (root, _, _) = self.root_entry(version).ok()?;
+        if root.is_null() {
             return None;
         }
 
-        let root = self.roots[version].0;
         let mut x = root;
-        unsafe {
-            loop {
-                let next = match k.cmp(&(*x.pointer).key) {
-                    Ordering::Less => x.left(version),
-                    _ => x.right(version),
-                };
-                if next.is_null() {
-                    break;
-                }
-                x = next;
+        loop {
+            let next = match k.cmp(self.arena.key(x)) {
+                Ordering::Less => self.arena.left(x, version),
+                _ => self.arena.right(x, version),
+            };
+            if next.is_null() {
+                break;
             }
+            x = next;
         }
 
         if x.is_null() {
             return None;
         }
 
-        unsafe {
-            if let Ordering::Less = k.cmp(&(*x.pointer).key) {
-                return Some(&(*x.pointer).value);
-            }
+        if let Ordering::Less = k.cmp(self.arena.key(x)) {
+            return Some(self.arena.value_at(x, version));
         }
 
-        let succ = self.successor_by_node(x, version);
+        let succ = self.successor_by_node_at(x, version);
         if succ.is_null() {
             return None;
         }
 
-        unsafe { Some(&(*succ.pointer).value) }
+        Some(self.arena.value_at(succ, version))
+    }
+
+    /// Greatest key at `version` that is less than or equal to `k`.
+    pub fn floor(&self, k: &K, version: usize) -> Option<(&K, &V)> {
+        self.nearest(k, version, true, true)
+    }
+
+    /// Smallest key at `version` that is greater than or equal to `k`.
+    pub fn ceiling(&self, k: &K, version: usize) -> Option<(&K, &V)> {
+        self.nearest(k, version, false, true)
+    }
+
+    /// Greatest key at `version` that is less than `k`, that does not need to
+    /// be there.
+    pub fn strict_predecessor(&self, k: &K, version: usize) -> Option<(&K, &V)> {
+        self.nearest(k, version, true, false)
+    }
+
+    /// Smallest key at `version` that is greater than `k`, that does not need
+    /// to be there.
+    pub fn strict_successor(&self, k: &K, version: usize) -> Option<(&K, &V)> {
+        self.nearest(k, version, false, false)
+    }
+
+    // Closest key to `k` below or above it, walking down from the root and
+    // keeping the last node that fits
+    fn nearest(&self, k: &K, version: usize, below: bool, inclusive: bool) -> Option<(&K, &V)> {
+        let mut x = self.get_root(version);
+        let mut found = NodePtr::null();
+        while !x.is_null() {
+            let ordering = k.cmp(self.arena.key(x));
+            if ordering == Ordering::Equal && inclusive {
+                found = x;
+                break;
+            }
+            let fits = match below {
+                true => ordering == Ordering::Greater,
+                false => ordering == Ordering::Less,
+            };
+            if fits {
+                found = x;
+            }
+            x = match fits == below {
+                true => self.arena.right(x, version),
+                false => self.arena.left(x, version),
+            };
+        }
+
+        if found.is_null() {
+            return None;
+        }
+
+        Some((self.arena.key(found), self.arena.value_at(found, version)))
     }
 
-    pub fn successor_by_node(&self, node: NodePtr<K, V>, version: usize) -> NodePtr<K, V> {
+    /// Position of `k`, counting from 0, in the order of the keys at
+    /// `version`, `None` when it is not there. The inverse of `select`.
+    pub fn rank(&self, k: &K, version: usize) -> Result<Option<usize>> {
+        let (mut x, _, _) = self.root_entry(version)?;
+        let mut rank = 0;
+        while !x.is_null() {
+            let left = self.arena.left(x, version);
+            x = match k.cmp(self.arena.key(x)) {
+                Ordering::Less => left,
+                Ordering::Equal => return Ok(Some(rank + self.arena.size(left, version))),
+                Ordering::Greater => {
+                    rank += self.arena.size(left, version) + 1;
+                    self.arena.right(x, version)
+                }
+            };
+        }
+        Ok(None)
+    }
+
+    /// Key at position `i`, counting from 0, in the order of the keys at
+    /// `version`, `None` when there are not that many.
+    pub fn select(&self, i: usize, version: usize) -> Result<Option<(&K, &V)>> {
+        let (mut x, _, _) = self.root_entry(version)?;
+        let mut i = i;
+        while !x.is_null() {
+            let left = self.arena.left(x, version);
+            let left_size = self.arena.size(left, version);
+            x = match i.cmp(&left_size) {
+                Ordering::Less => left,
+                Ordering::Equal => {
+                    return Ok(Some((self.arena.key(x), self.arena.value_at(x, version))));
+                }
+                Ordering::Greater => {
+                    i -= left_size + 1;
+                    self.arena.right(x, version)
+                }
+            };
+        }
+        Ok(None)
+    }
+
+    /// Middle key at `version`, the lower one when there is an even amount,
+    /// `None` when it is empty.
+    pub fn median(&self, version: usize) -> Result<Option<(&K, &V)>> {
+        let (_, len, _) = self.root_entry(version)?;
+        match len.checked_sub(1) {
+            Some(last) => self.select(last / 2, version),
+            None => Ok(None),
+        }
+    }
+
+    pub fn successor_by_node(&self, node: NodePtr, version: usize) -> NodePtr {
+        if self.root_entry(version).is_err() {
+            return NodePtr::null();
+        }
+
+        self.successor_by_node_at(node, version)
+    }
+
+    fn successor_by_node_at(&self, node: NodePtr, version: usize) -> NodePtr {
         let mut x = node;
-        if !node.right(version).is_null() {
-            return x.right(version).min_node(version);
+        if !self.arena.right(node, version).is_null() {
+            return self.arena.min_node(self.arena.right(x, version), version);
         }
 
-        let mut y = x.parent(version);
-        while !y.is_null() && x.is_right_child(version) {
+        let mut y = self.arena.parent(x, version);
+        while !y.is_null() && self.arena.is_right_child(x, version) {
             x = y;
-            y = x.parent(version);
+            y = self.arena.parent(x, version);
         }
         y
     }
 
-    unsafe fn left_rotate(&mut self, node: NodePtr<K, V>) {
+    fn left_rotate(&mut self, node: NodePtr) {
         let version = self.curr_version;
-        let mut caba = node;
-        let mut temp = caba.right(version);
-        caba.set_right(temp.left(version), version);
-
-        if !temp.left(version).is_null() {
-            temp.left(version).set_parent(caba, version);
+        let caba = node;
+        let temp = self.arena.right(caba, version);
+        self.arena
+            .set_right(caba, self.arena.left(temp, version), version);
+
+        if !self.arena.left(temp, version).is_null() {
+            self.arena
+                .set_parent(self.arena.left(temp, version), caba, version);
         }
 
-        temp.set_parent(caba.parent(version), version);
-        if caba.parent(version).is_null() {
+        self.arena
+            .set_parent(temp, self.arena.parent(caba, version), version);
+        if self.arena.parent(caba, version).is_null() {
             self.root = temp;
-        } else if caba.is_left_child(version) {
-            caba.parent(version).set_left(temp, version);
+        } else if self.arena.is_left_child(caba, version) {
+            self.arena
+                .set_left(self.arena.parent(caba, version), temp, version);
         } else {
-            caba.parent(version).set_right(temp, version);
+            self.arena
+                .set_right(self.arena.parent(caba, version), temp, version);
         }
 
-        temp.set_left(caba, version);
-        caba.set_parent(temp, version);
+        self.arena.set_left(temp, caba, version);
+        self.arena.set_parent(caba, temp, version);
+        self.arena.update_size(caba, version);
+        self.arena.update_size(temp, version);
     }
 
-    unsafe fn right_rotate(&mut self, node: NodePtr<K, V>) {
+    fn right_rotate(&mut self, node: NodePtr) {
         let version = self.curr_version;
-        let mut caba = node;
-        let mut temp = caba.left(version);
-        caba.set_left(temp.right(version), version);
-
-        if !temp.right(version).is_null() {
-            temp.right(version).set_parent(caba, version);
+        let caba = node;
+        let temp = self.arena.left(caba, version);
+        self.arena
+            .set_left(caba, self.arena.right(temp, version), version);
+
+        if !self.arena.right(temp, version).is_null() {
+            self.arena
+                .set_parent(self.arena.right(temp, version), caba, version);
         }
 
-        temp.set_parent(caba.parent(version), version);
-        if caba.parent(version).is_null() {
+        self.arena
+            .set_parent(temp, self.arena.parent(caba, version), version);
+        if self.arena.parent(caba, version).is_null() {
             self.root = temp;
-        } else if caba.is_right_child(version) {
-            caba.parent(version).set_right(temp, version);
+        } else if self.arena.is_right_child(caba, version) {
+            self.arena
+                .set_right(self.arena.parent(caba, version), temp, version);
         } else {
-            caba.parent(version).set_left(temp, version);
+            self.arena
+                .set_left(self.arena.parent(caba, version), temp, version);
         }
 
-        temp.set_right(caba, version);
-        caba.set_parent(temp, version);
+        self.arena.set_right(temp, caba, version);
+        self.arena.set_parent(caba, temp, version);
+        self.arena.update_size(caba, version);
+        self.arena.update_size(temp, version);
     }
 
-    unsafe fn insert_fixup(&mut self, node: NodePtr<K, V>) {
+    fn insert_fixup(&mut self, node: NodePtr) {
         let version = self.curr_version;
         let mut dude = node;
-        while dude != self.root && dude.parent(version).is_red_color(version) {
-            if dude.parent(version).is_left_child(version) {
-                let mut uncle = dude.parent(version).parent(version).right(version);
+        while !self.arena.same(dude, self.root, version)
+            && self
+                .arena
+                .is_red_color(self.arena.parent(dude, version), version)
+        {
+            let parent = self.arena.parent(dude, version);
+            let grandparent = self.arena.parent(parent, version);
+            if self.arena.is_left_child(parent, version) {
+                let uncle = self.arena.right(grandparent, version);
 
                 // Case 1
-                if uncle.is_red_color(version) {
-                    dude.parent(version).set_black_color(version);
-                    uncle.set_black_color(version);
-                    dude.parent(version).parent(version).set_red_color(version);
-                    dude = dude.parent(version).parent(version);
+                if self.arena.is_red_color(uncle, version) {
+                    self.arena.set_black_color(parent, version);
+                    self.arena.set_black_color(uncle, version);
+                    self.arena.set_red_color(grandparent, version);
+                    dude = self.arena.get_last_copy(grandparent, version);
                     continue;
                 }
 
                 // Case 2
-                if dude.is_right_child(version) {
-                    dude = dude.parent(version);
+                if self.arena.is_right_child(dude, version) {
+                    dude = parent;
                     self.left_rotate(dude);
                 }
 
                 // Case 3
-                dude.parent(version).set_black_color(version);
-                dude.parent(version).parent(version).set_red_color(version);
-                self.right_rotate(dude.parent(version).parent(version));
+                let parent = self.arena.parent(dude, version);
+                let grandparent = self.arena.parent(parent, version);
+                self.arena.set_black_color(parent, version);
+                self.arena.set_red_color(grandparent, version);
+                self.right_rotate(grandparent);
             } else {
-                let mut uncle = dude.parent(version).parent(version).left(version);
+                let uncle = self.arena.left(grandparent, version);
 
                 // Case 4
-                if uncle.is_red_color(version) {
-                    uncle.set_black_color(version);
-                    dude.parent(version).set_black_color(version);
-                    dude.parent(version).parent(version).set_red_color(version);
-                    dude = dude.parent(version).parent(version);
+                if self.arena.is_red_color(uncle, version) {
+                    self.arena.set_black_color(uncle, version);
+                    self.arena.set_black_color(parent, version);
+                    self.arena.set_red_color(grandparent, version);
+                    dude = self.arena.get_last_copy(grandparent, version);
                     continue;
                 }
 
                 // Case 5
-                if dude.is_left_child(version) {
-                    dude = dude.parent(version);
+                if self.arena.is_left_child(dude, version) {
+                    dude = parent;
                     self.right_rotate(dude);
                 }
 
                 // Case 6
-                dude.parent(version).set_black_color(version);
-                dude.parent(version).parent(version).set_red_color(version);
-                self.left_rotate(dude.parent(version).parent(version));
+                let parent = self.arena.parent(dude, version);
+                let grandparent = self.arena.parent(parent, version);
+                self.arena.set_black_color(parent, version);
+                self.arena.set_red_color(grandparent, version);
+                self.left_rotate(grandparent);
             }
         }
-        let mut possible_new_root = self.root;
-        possible_new_root.set_black_color(version);
-        self.root = possible_new_root.get_last_copy(version);
+        self.arena.set_black_color(self.root, version);
+        self.root = self.arena.get_last_copy(self.root, version);
     }
 
-    pub fn insert(&mut self, k: K, v: V) {
-        self.len += 1;
-        let new_length = self.len;
-        let node = NodePtr::new(k, v);
-        let mut y = NodePtr::null();
-        let mut x = self.roots[self.curr_version].0;
-        self.curr_version += 1;
+    /// Inserts in a new version derived from the latest one, a key already
+    /// there is handled as the duplicate policy says.
+    pub fn insert(&mut self, k: K, v: V) -> InsertOutcome {
+        self.insert_from(self.curr_version, k, v)
+    }
+
+    /// Inserts in a new version derived from `version`, that can be any
+    /// version in history, branching the version tree there.
+    pub fn insert_at(&mut self, version: usize, k: K, v: V) -> Result<InsertOutcome> {
+        self.root_entry(version)?;
+        Ok(self.insert_from(version, k, v))
+    }
 
-        unsafe {
-            (*node.pointer).version = self.curr_version;
+    fn insert_from(&mut self, from: usize, k: K, v: V) -> InsertOutcome {
+        let node = self.find_node(&k, from);
+        if node.is_null() {
+            self.insert_node(from, k, v);
+            return InsertOutcome::Inserted;
         }
 
-        while !x.is_null() {
-            y = x;
-            match node.cmp(&x) {
-                Ordering::Less => {
-                    x = x.left(self.curr_version);
+        match self.policy {
+            DuplicatePolicy::Reject => InsertOutcome::Rejected,
+            DuplicatePolicy::Replace => {
+                self.replace_value(from, node, v);
+                InsertOutcome::Replaced
+            }
+            DuplicatePolicy::Multi => {
+                let count = self.arena.count(node, from) + 1;
+                self.recount(from, node, count);
+                InsertOutcome::Counted
+            }
+        }
+    }
+
+    // Links a new node for `k`, that must not be there yet
+    fn insert_node(&mut self, from: usize, k: K, v: V) {
+        self.branch(from);
+        self.link_node(k, v);
+        self.publish(from);
+    }
+
+    // Links a new node for `k` in the version in progress
+    fn link_node(&mut self, k: K, v: V) -> NodePtr {
+        let version = self.curr_version;
+        let mut y = NodePtr::null();
+        let mut x = self.root;
+
+        while !x.is_null() {
+            y = x;
+            match k.cmp(self.arena.key(x)) {
+                Ordering::Less => {
+                    x = self.arena.left(x, version);
                 }
                 _ => {
-                    x = x.right(self.curr_version);
+                    x = self.arena.right(x, version);
                 }
             };
         }
-        unsafe {
-            (*node.pointer).parent = self.nil;
-            (*node.pointer).left = self.nil;
-            (*node.pointer).right = self.nil;
+
+        self.link_below(y, k, v)
+    }
+
+    // Links a new node for `k` as a child of `y`, the last node on its search
+    // path or null for an empty tree, in the version in progress
+    fn link_below(&mut self, y: NodePtr, k: K, v: V) -> NodePtr {
+        self.len += 1;
+        let version = self.curr_version;
+        let y = self.arena.get_last_copy(y, version);
+        let mut node = GojoNode::new(k, v);
+        node.version = version;
+        node.stamp = version;
+        if !y.is_null() {
+            node.parent = y;
+            node.bk_ptr_parent = y;
         }
+        let goes_left = !y.is_null() && node.key < *self.arena.key(y);
+        let node = self.arena.alloc(node);
 
         if y.is_null() {
             self.root = node;
+        } else if goes_left {
+            self.arena.set_left(y, node, version);
         } else {
-            unsafe {
-                (*node.pointer).parent = y;
-                (*node.pointer).bk_ptr_parent = y;
-            }
-            match node.cmp(&y) {
-                Ordering::Less => {
-                    y.set_left(node, self.curr_version);
+            self.arena.set_right(y, node, version);
+        }
+
+        self.update_sizes_up(y);
+        self.insert_fixup(node);
+        node
+    }
+
+    // Makes version 1 out of `pairs`, on a tree that has only version 0. Nodes
+    // are allocated in key order, so every subtree is a range of the arena
+    fn build_sorted(&mut self, pairs: impl Iterator<Item = (K, V)>) -> Result<()> {
+        self.branch(0);
+        let version = self.curr_version;
+        let first = self.arena.nodes.len();
+        for (position, (k, v)) in pairs.enumerate() {
+            match self.arena.nodes[first..].last_mut() {
+                Some(last) if k < last.key => {
+                    anyhow::bail!(GojoError::UnsortedKeys(format!("{position}")));
                 }
+                Some(last) if k == last.key => match self.policy {
+                    DuplicatePolicy::Reject => {}
+                    DuplicatePolicy::Replace => last.value = v,
+                    DuplicatePolicy::Multi => last.count += 1,
+                },
                 _ => {
-                    y.set_right(node, self.curr_version);
+                    let mut node = GojoNode::new(k, v);
+                    node.version = version;
+                    node.stamp = version;
+                    self.arena.alloc(node);
                 }
-            };
+            }
+        }
+
+        // Leaves are at the last two depths at most, the deepest ones are red
+        // so that every path has the same black nodes
+        let len = self.arena.nodes.len() - first;
+        let red_depth = match len {
+            0 | 1 => None,
+            len => Some(len.ilog2() as usize),
+        };
+        self.len = len;
+        self.root = self.link_sorted(first, first + len, NodePtr::null(), 0, red_depth);
+        self.publish(0);
+        Ok(())
+    }
+
+    // Links the nodes in `lo..hi` of the arena as a balanced subtree, hanging
+    // from `parent` at `depth`, and returns its root
+    fn link_sorted(
+        &mut self,
+        lo: usize,
+        hi: usize,
+        parent: NodePtr,
+        depth: usize,
+        red_depth: Option<usize>,
+    ) -> NodePtr {
+        if lo == hi {
+            return NodePtr::null();
         }
+        let mid = lo + (hi - lo) / 2;
+        let ptr = NodePtr(mid as u32);
+        let left = self.link_sorted(lo, mid, ptr, depth + 1, red_depth);
+        let right = self.link_sorted(mid + 1, hi, ptr, depth + 1, red_depth);
+
+        let version = self.curr_version;
+        let own = M::lift(self.arena.key(ptr), &self.arena.node(ptr).value);
+        let agg = M::combine(
+            &M::combine(&self.arena.agg(left, version), &own),
+            &self.arena.agg(right, version),
+        );
+        let node = self.arena.node_mut(ptr);
+        node.agg = agg;
+        node.left = left;
+        node.bk_ptr_left = left;
+        node.right = right;
+        node.bk_ptr_right = right;
+        node.parent = parent;
+        node.bk_ptr_parent = parent;
+        node.size = hi - lo;
+        node.color = match red_depth == Some(depth) {
+            true => Color::Red,
+            false => Color::Black,
+        };
+        ptr
+    }
 
-        unsafe {
-            self.insert_fixup(node);
+    /// Inserts the key in a new version or, when it is already there, records
+    /// its new value in a new version. Returns the value it had before.
+    pub fn insert_or_replace(&mut self, k: K, v: V) -> Option<V> {
+        let from = self.curr_version;
+        let node = self.find_node(&k, from);
+        if node.is_null() {
+            self.insert_node(from, k, v);
+            return None;
         }
 
-        self.roots.push((self.root, new_length));
+        let old = self.arena.value_at(node, from).clone();
+        self.replace_value(from, node, v);
+        Some(old)
     }
 
-    pub fn find_node(&self, k: &K, version: usize) -> NodePtr<K, V> {
-        if version > self.curr_version {
-            return NodePtr::null();
+    /// Records `f` applied to the value of `k` in a new version, no version is
+    /// created if the key is not there.
+    pub fn update<F: FnOnce(&V) -> V>(&mut self, k: &K, f: F) -> bool {
+        let from = self.curr_version;
+        let node = self.find_node(k, from);
+        if node.is_null() {
+            return false;
         }
 
-        let root = self.roots[version].0;
-        let mut temp = root;
-        unsafe {
-            loop {
-                let next = match k.cmp(&(*temp.pointer).key) {
-                    Ordering::Less => temp.left(version),
-                    Ordering::Greater => temp.right(version),
-                    Ordering::Equal => return temp,
-                };
-                if next.is_null() {
-                    break;
-                }
-                temp = next;
+        let value = f(self.arena.value_at(node, from));
+        self.replace_value(from, node, value);
+        true
+    }
+
+    fn replace_value(&mut self, from: usize, node: NodePtr, v: V) {
+        self.branch(from);
+        let version = self.curr_version;
+        self.arena.set_value(node, v, version);
+        self.update_aggs_up(node);
+        self.publish(from);
+    }
+
+    fn recount(&mut self, from: usize, node: NodePtr, count: usize) {
+        self.branch(from);
+        let version = self.curr_version;
+        self.arena.set_count(node, count, version);
+        self.publish(from);
+    }
+
+    // Stamps every node the new version changed up to the root, and records
+    // its root. A node already stamped had its ancestors stamped with it
+    fn publish(&mut self, from: usize) {
+        let version = self.curr_version;
+        for node in mem::take(&mut self.arena.touched) {
+            let mut x = self.arena.get_last_copy(node, version);
+            while !x.is_null() && self.arena.stamp(x, version) != version {
+                self.arena.set_stamp(x, version);
+                x = self.arena.parent(x, version);
             }
         }
+        self.arena.touched.clear();
+        self.root = self.arena.get_last_copy(self.root, version);
+        self.roots.push((self.root, self.len, Some(from)));
+    }
+
+    // Starts a new latest version derived from `from`
+    fn branch(&mut self, from: usize) {
+        self.curr_version = self.arena.insert_version(from);
+        let (root, len, _) = self.roots[from - self.first_version];
+        self.root = self.arena.get_last_copy(root, self.curr_version);
+        self.len = len;
+    }
+
+    pub fn find_node(&self, k: &K, version: usize) -> NodePtr {
+        self.find_below(self.get_root(version), k, version)
+    }
+
+    // Node of `k` in the subtree of `node`, as seen from `version`
+    fn find_below(&self, node: NodePtr, k: &K, version: usize) -> NodePtr {
+        let mut temp = node;
+        while !temp.is_null() {
+            temp = match k.cmp(self.arena.key(temp)) {
+                Ordering::Less => self.arena.left(temp, version),
+                Ordering::Greater => self.arena.right(temp, version),
+                Ordering::Equal => return temp,
+            };
+        }
         NodePtr::null()
     }
 
@@ -1139,7 +2773,18 @@ impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Gojo<K, V> {
             return None;
         }
 
-        unsafe { Some(&(*node.pointer).value) }
+        Some(self.arena.value_at(node, version))
+    }
+
+    /// Copies of `k` at `version`, only `DuplicatePolicy::Multi` keeps more
+    /// than one.
+    pub fn count(&self, k: &K, version: usize) -> usize {
+        let node = self.find_node(k, version);
+        if node.is_null() {
+            return 0;
+        }
+
+        self.arena.count(node, version)
     }
 
     pub fn contains_key(&self, k: &K, version: usize) -> bool {
@@ -1150,284 +2795,663 @@ impl<K: Ord + Clone + Default + Debug, V: Clone + Default + Debug> Gojo<K, V> {
         true
     }
 
-    fn clear_recurse(&mut self, current: NodePtr<K, V>) {
-        if !current.is_null() {
-            unsafe {
-                self.clear_recurse(current.left(self.curr_version));
-                self.clear_recurse(current.right(self.curr_version));
-                let _ = Box::from_raw(current.pointer);
-            }
+    /// Copies the tree of `version` alone into a new `Gojo`, where it is
+    /// version 0, leaving out the history it came from.
+    pub fn snapshot(&self, version: usize) -> Result<Gojo<K, V, MODS, M>> {
+        let (root, len, _) = self.root_entry(version)?;
+        let mut snapshot = Gojo::with_mods(DEFAULT_MAX_OPS, self.policy);
+        if !root.is_null() {
+            snapshot.root = self.arena.deep_clone(root, version, &mut snapshot.arena);
         }
+        snapshot.len = len;
+        snapshot.roots[0] = (snapshot.root, len, None);
+        Ok(snapshot)
     }
 
+    /// Frees the nodes of every version, going back to an empty version 0.
     pub fn clear(&mut self) {
-        let root = self.root;
-        self.root = NodePtr::null();
-        self.clear_recurse(root);
-        self.len = 0;
-        self.roots = Vec::new();
+        *self = Gojo::with_mods(self.roots.capacity(), self.policy);
     }
 
+    /// Same as `clear`, but keeps the memory already allocated to be reused.
     pub fn fast_clear(&mut self) {
         self.root = NodePtr::null();
         self.len = 0;
-        self.roots = Vec::new();
+        self.curr_version = 0;
+        self.first_version = 0;
+        self.roots.clear();
+        self.roots.push((NodePtr::null(), 0, None));
+        self.arena.nodes.clear();
+        self.arena.chain_of.clear();
+        self.arena.enclosing.clear();
+        self.arena.chains.clear();
+        self.arena.starting_at.clear();
+        self.arena.versions = VersionList::new(self.roots.capacity());
+        self.labels.clear();
+        self.timestamps.clear();
+    }
+
+    /// Discards every version before `keep_from`, freeing the nodes and mods
+    /// only they needed. Queries on them fail with `GojoError::VersionPruned`.
+    pub fn truncate_history(&mut self, keep_from: usize) -> Result<()> {
+        if keep_from > self.latest_version() {
+            anyhow::bail!(GojoError::UnknownVersion(format!("{keep_from}")));
+        }
+        if keep_from <= self.first_version {
+            return Ok(());
+        }
+
+        self.roots.drain(..keep_from - self.first_version);
+        self.first_version = keep_from;
+        self.labels.retain(|_, version| *version >= keep_from);
+        let pruned = self.timestamps.partition_point(|(v, _)| *v < keep_from);
+        self.timestamps.drain(..pruned);
+        let roots: Vec<NodePtr> = self.roots.iter().map(|(root, _, _)| *root).collect();
+        let moved = self.arena.truncate(keep_from, &roots);
+        let remap = |ptr: NodePtr| match ptr.is_null() {
+            true => ptr,
+            false => moved[ptr.index()],
+        };
+        for (root, _, _) in self.roots.iter_mut() {
+            *root = remap(*root);
+        }
+        self.root = remap(self.root);
+        Ok(())
     }
 
+    /// Removes in a new version derived from the latest one, no version is
+    /// created if the key is not there. With `DuplicatePolicy::Multi` only one
+    /// copy of the key is taken away.
     pub fn remove(&mut self, k: &K) -> Option<V> {
-        let node = self.find_node(k, self.curr_version);
+        self.remove_from(self.curr_version, k)
+    }
+
+    /// Removes in a new version derived from `version`, that can be any
+    /// version in history, branching the version tree there.
+    pub fn remove_at(&mut self, version: usize, k: &K) -> Result<Option<V>> {
+        self.root_entry(version)?;
+        Ok(self.remove_from(version, k))
+    }
+
+    fn remove_from(&mut self, from: usize, k: &K) -> Option<V> {
+        let node = self.find_node(k, from);
         if node.is_null() {
             return None;
         }
 
-        self.len -= 1;
-        let new_length = self.len;
-        self.curr_version += 1;
-        self.roots.push((self.root, new_length));
+        let count = self.arena.count(node, from);
+        if count > 1 {
+            let value = self.arena.value_at(node, from).clone();
+            self.recount(from, node, count - 1);
+            return Some(value);
+        }
+
+        Some(self.remove_node(from, node))
+    }
+
+    // Unlinks `node`, whatever its count is
+    fn remove_node(&mut self, from: usize, node: NodePtr) -> V {
+        self.branch(from);
+        let value = self.unlink_node(node);
+        self.publish(from);
+        value
+    }
 
-        let key = unsafe { Some(self.delete(node).1) };
-        self.root = self.root.get_last_copy(self.curr_version);
-        self.roots[self.curr_version].0 = self.root.get_last_copy(self.curr_version);
-        key
+    // Unlinks `node` in the version in progress
+    fn unlink_node(&mut self, node: NodePtr) -> V {
+        self.len -= 1;
+        let node = self.arena.get_last_copy(node, self.curr_version);
+        self.delete(node).1
     }
 
     pub fn college_remove(&mut self, k: &K) -> Option<V> {
-        let node = self.find_node(k, self.curr_version);
-        if node.is_null() {
-            self.curr_version += 1;
-            self.roots.push((self.root, self.len));
+        self.remove_or_branch(self.curr_version, k)
+    }
+
+    // Same as `remove_from`, but still creates a version if `k` is not there
+    fn remove_or_branch(&mut self, from: usize, k: &K) -> Option<V> {
+        if self.find_node(k, from).is_null() {
+            self.unchanged_version(from);
             return None;
         }
 
-        self.len -= 1;
-        let new_length = self.len;
-        self.curr_version += 1;
-        self.roots.push((self.root, new_length));
+        self.remove_from(from, k)
+    }
 
-        let key = unsafe { Some(self.delete(node).1) };
-        self.root = self.root.get_last_copy(self.curr_version);
-        self.roots[self.curr_version].0 = self.root.get_last_copy(self.curr_version);
-        key
+    // Creates a version derived from `from` with the same tree
+    fn unchanged_version(&mut self, from: usize) {
+        self.branch(from);
+        self.roots.push((self.root, self.len, Some(from)));
     }
 
-    unsafe fn delete_fixup(&mut self, caba: NodePtr<K, V>) {
+    // Without a sentinel node `x` can be null, so its parent is tracked
+    // alongside it instead of being read from it
+    fn delete_fixup(&mut self, caba: NodePtr, caba_parent: NodePtr) {
         let mut x = caba;
+        let mut x_parent = caba_parent;
         let version = self.curr_version;
 
-        while x != self.root && x.is_black_color(version) {
-            if x.is_left_child(version) {
-                let mut brother = x.parent(version).right(version);
+        while !self.arena.same(x, self.root, version) && self.arena.is_black_color(x, version) {
+            if self
+                .arena
+                .same(x, self.arena.left(x_parent, version), version)
+            {
+                let mut brother = self.arena.right(x_parent, version);
 
                 // Case 1
-                if brother.is_red_color(version) {
-                    brother.set_black_color(version);
-                    x.parent(version).set_red_color(version);
-                    self.left_rotate(x.parent(version));
-                    brother = x.parent(version).right(version);
+                if self.arena.is_red_color(brother, version) {
+                    self.arena.set_black_color(brother, version);
+                    self.arena.set_red_color(x_parent, version);
+                    self.left_rotate(x_parent);
+                    brother = self.arena.right(x_parent, version);
                 }
 
                 // Case 2
-                if brother.left(version).is_black_color(version)
-                    && brother.right(version).is_black_color(version)
+                if self
+                    .arena
+                    .is_black_color(self.arena.left(brother, version), version)
+                    && self
+                        .arena
+                        .is_black_color(self.arena.right(brother, version), version)
                 {
-                    brother.set_red_color(version);
-                    x = x.parent(version);
+                    self.arena.set_red_color(brother, version);
+                    x = x_parent;
+                    x_parent = self.arena.parent(x, version);
                 }
                 // Case 3
                 else {
-                    if brother.right(version).is_black_color(version) {
-                        brother.left(version).set_black_color(version);
-                        brother.set_red_color(version);
+                    if self
+                        .arena
+                        .is_black_color(self.arena.right(brother, version), version)
+                    {
+                        self.arena
+                            .set_black_color(self.arena.left(brother, version), version);
+                        self.arena.set_red_color(brother, version);
                         self.right_rotate(brother);
-                        brother = x.parent(version).right(version);
+                        brother = self.arena.right(x_parent, version);
                     }
 
                     // Case 4
-                    brother.set_color(x.parent(version).get_color(version), version);
-                    x.parent(version).set_black_color(version);
-                    brother.right(version).set_black_color(version);
-                    self.left_rotate(x.parent(version));
+                    self.arena
+                        .set_color(brother, self.arena.get_color(x_parent, version), version);
+                    self.arena.set_black_color(x_parent, version);
+                    self.arena
+                        .set_black_color(self.arena.right(brother, version), version);
+                    self.left_rotate(x_parent);
                     x = self.root;
                 }
             } else {
-                let mut brother = x.parent(version).left(version);
+                let mut brother = self.arena.left(x_parent, version);
 
                 // Case 1
-                if brother.is_red_color(version) {
-                    brother.set_black_color(version);
-                    x.parent(version).set_red_color(version);
-                    self.right_rotate(x.parent(version));
-                    brother = x.parent(version).left(version);
+                if self.arena.is_red_color(brother, version) {
+                    self.arena.set_black_color(brother, version);
+                    self.arena.set_red_color(x_parent, version);
+                    self.right_rotate(x_parent);
+                    brother = self.arena.left(x_parent, version);
                 }
 
                 // Case 2
-                if brother.right(version).is_black_color(version)
-                    && brother.left(version).is_black_color(version)
+                if self
+                    .arena
+                    .is_black_color(self.arena.right(brother, version), version)
+                    && self
+                        .arena
+                        .is_black_color(self.arena.left(brother, version), version)
                 {
-                    brother.set_red_color(version);
-                    x = x.parent(version);
+                    self.arena.set_red_color(brother, version);
+                    x = x_parent;
+                    x_parent = self.arena.parent(x, version);
                 } else {
                     // Case 3
-                    if brother.left(version).is_black_color(version) {
-                        brother.right(version).set_black_color(version);
-                        brother.set_red_color(version);
+                    if self
+                        .arena
+                        .is_black_color(self.arena.left(brother, version), version)
+                    {
+                        self.arena
+                            .set_black_color(self.arena.right(brother, version), version);
+                        self.arena.set_red_color(brother, version);
                         self.left_rotate(brother);
-                        brother = x.parent(version).left(version);
+                        brother = self.arena.left(x_parent, version);
                     }
 
                     // Case 4
-                    brother.set_color(x.parent(version).get_color(version), version);
-                    x.parent(version).set_black_color(version);
-                    brother.left(version).set_black_color(version);
-                    self.right_rotate(x.parent(version));
+                    self.arena
+                        .set_color(brother, self.arena.get_color(x_parent, version), version);
+                    self.arena.set_black_color(x_parent, version);
+                    self.arena
+                        .set_black_color(self.arena.left(brother, version), version);
+                    self.right_rotate(x_parent);
                     x = self.root;
                 }
             }
         }
 
-        x.set_black_color(version);
+        self.arena.set_black_color(x, version);
     }
 
-    unsafe fn delete(&mut self, z: NodePtr<K, V>) -> (K, V) {
+    fn delete(&mut self, z: NodePtr) -> (K, V) {
         let version = self.curr_version;
 
-        let mut y = z;
-        let mut x;
-        let mut original_y_color = y.get_color(version);
+        let x;
+        let x_parent;
+        let original_y_color;
 
-        if z.left(version).is_null() {
-            x = z.right(version);
+        if self.arena.left(z, version).is_null() {
+            x = self.arena.right(z, version);
+            x_parent = self.arena.parent(z, version);
+            original_y_color = self.arena.get_color(z, version);
             self.transplant(z, x);
-        } else if z.right(version).is_null() {
-            x = z.left(version);
+        } else if self.arena.right(z, version).is_null() {
+            x = self.arena.left(z, version);
+            x_parent = self.arena.parent(z, version);
+            original_y_color = self.arena.get_color(z, version);
             self.transplant(z, x)
         } else {
-            y = self.successor_by_node(z, version);
-            x = y.right(version);
-            original_y_color = y.get_color(version);
+            let y = self.successor_by_node_at(z, version);
+            x = self.arena.right(y, version);
+            original_y_color = self.arena.get_color(y, version);
 
-            if y.parent(version) == x {
-                x.set_parent(y, version);
+            if self.arena.same(self.arena.parent(y, version), z, version) {
+                x_parent = y;
+                self.arena.set_parent(x, y, version);
             } else {
+                x_parent = self.arena.parent(y, version);
                 self.transplant(y, x);
-                y.set_right(z.right(version), version);
-                y.right(version).set_parent(y, version);
+                self.arena
+                    .set_right(y, self.arena.right(z, version), version);
+                self.arena
+                    .set_parent(self.arena.right(y, version), y, version);
             }
 
             self.transplant(z, y);
-            y.set_left(z.left(version), version);
-            z.left(version).set_parent(y, version);
+            self.arena.set_left(y, self.arena.left(z, version), version);
+            self.arena
+                .set_parent(self.arena.left(z, version), y, version);
 
-            y.set_color(z.get_color(version), version);
+            self.arena
+                .set_color(y, self.arena.get_color(z, version), version);
         }
 
+        self.update_sizes_up(x_parent);
         if original_y_color == Color::Black {
-            self.delete_fixup(x);
+            self.delete_fixup(x, x_parent);
         }
 
-        let key = (*y.pointer).key.clone();
-        let value = (*y.pointer).value.clone();
-        if y.pointer.is_null() {
-            drop_in_place(y.pointer);
-        }
+        let key = self.arena.key(z).clone();
+        let value = self.arena.value_at(z, version).clone();
         (key, value)
     }
 
-    fn transplant(&mut self, u: NodePtr<K, V>, other: NodePtr<K, V>) {
+    // Sets the sizes from `node` up to the root, after a node was linked or
+    // unlinked right below it
+    fn update_sizes_up(&mut self, node: NodePtr) {
+        let version = self.curr_version;
+        let mut x = node;
+        while !x.is_null() {
+            self.arena.update_size(x, version);
+            x = self.arena.parent(x, version);
+        }
+    }
+
+    // Sets the summaries from `node` up to the root, after its value changed
+    fn update_aggs_up(&mut self, node: NodePtr) {
+        let version = self.curr_version;
+        let mut x = node;
+        while !x.is_null() {
+            self.arena.update_agg(x, version);
+            x = self.arena.parent(x, version);
+        }
+    }
+
+    fn transplant(&mut self, u: NodePtr, v: NodePtr) {
         let version = self.curr_version;
-        let mut v = other;
 
-        if u.parent(version).is_null() {
+        let parent = self.arena.parent(u, version);
+        if parent.is_null() {
             self.root = v;
-        } else if u.is_left_child(version) {
-            u.parent(version).set_left(v, version)
+        } else if self.arena.is_left_child(u, version) {
+            self.arena.set_left(parent, v, version)
         } else {
-            u.parent(version).set_right(v, version)
+            self.arena.set_right(parent, v, version)
         }
 
-        v.set_parent(u.parent(version), version);
+        self.arena
+            .set_parent(v, self.arena.parent(u, version), version);
     }
 
-    fn first_child(&self, root: NodePtr<K, V>, version: usize) -> NodePtr<K, V> {
+    fn first_child(&self, root: NodePtr, version: usize) -> NodePtr {
         if root.is_null() {
             NodePtr::null()
         } else {
-            let mut temp = root;
-            while !temp.left(version).is_null() {
-                temp = temp.left(version);
-            }
-            temp
+            self.arena.min_node(root, version)
         }
     }
 
-    fn last_child(&self, root: NodePtr<K, V>, version: usize) -> NodePtr<K, V> {
+    fn last_child(&self, root: NodePtr, version: usize) -> NodePtr {
         if root.is_null() {
             NodePtr::null()
         } else {
-            let mut temp = root;
-            while !temp.right(version).is_null() {
-                temp = temp.right(version);
-            }
-            temp
+            self.arena.max_node(root, version)
         }
     }
 
-    pub fn iter(&self, version: usize) -> Result<GojoIter<K, V>> {
-        if version > self.latest_version() {
-            anyhow::bail!(GojoError::UnknownVersion(format!("{version}")));
-        }
-        let root = self.roots[version].0;
-        let len = self.roots[version].1;
+    pub fn iter(&self, version: usize) -> Result<GojoIter<'_, K, V, MODS, M>> {
+        let (root, len, _) = self.root_entry(version)?;
         Ok(GojoIter {
+            arena: &self.arena,
             head: self.first_child(root, version),
             tail: self.last_child(root, version),
             len,
             version,
-            _marker: marker::PhantomData,
         })
     }
 
-    pub fn node_info_iter(&self, version: usize) -> Result<NodeInfoIter<K, V>> {
-        if version > self.latest_version() {
-            anyhow::bail!(GojoError::UnknownVersion(format!("{version}")));
+    /// Iterates over the keys inside `range` as they were at `version`.
+    pub fn range<R: RangeBounds<K>>(
+        &self,
+        version: usize,
+        range: R,
+    ) -> Result<GojoRange<'_, K, V, MODS, M>> {
+        let (root, _, _) = self.root_entry(version)?;
+        Ok(GojoRange {
+            arena: &self.arena,
+            head: self.lower_bound(root, range.start_bound(), version),
+            end: range.end_bound().cloned(),
+            version,
+        })
+    }
+
+    /// Counts the keys inside `range` as they were at `version`, in O(log n)
+    /// from the sizes of the subtrees.
+    pub fn count_range<R: RangeBounds<K>>(&self, version: usize, range: R) -> Result<usize> {
+        let (root, _, _) = self.root_entry(version)?;
+        let before_start = self.count_while(root, version, |key| match range.start_bound() {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        });
+        let up_to_end = self.count_while(root, version, |key| match range.end_bound() {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        });
+        Ok(up_to_end.saturating_sub(before_start))
+    }
+
+    // Amount of keys, from the smallest one, for which `holds` is true, going
+    // down a single path as it turns false from some key on
+    fn count_while(&self, root: NodePtr, version: usize, holds: impl Fn(&K) -> bool) -> usize {
+        let mut x = root;
+        let mut count = 0;
+        while !x.is_null() {
+            let left = self.arena.left(x, version);
+            if holds(self.arena.key(x)) {
+                count += self.arena.size(left, version) + 1;
+                x = self.arena.right(x, version);
+            } else {
+                x = left;
+            }
+        }
+        count
+    }
+
+    /// Summary of the keys inside `range` as they were at `version`, in
+    /// O(log n) from the ones every node keeps for its subtree.
+    pub fn aggregate<R: RangeBounds<K>>(&self, version: usize, range: R) -> Result<M::Summary> {
+        let (root, _, _) = self.root_entry(version)?;
+        let root = self.arena.get_last_copy(root, version);
+        Ok(self.aggregate_below(root, version, &range, false, false))
+    }
+
+    // Summary of the keys of the subtree of `x` inside `range`. A side that is
+    // open is already known to be inside it, a subtree open on both sides
+    // gives its whole summary
+    fn aggregate_below<R: RangeBounds<K>>(
+        &self,
+        x: NodePtr,
+        version: usize,
+        range: &R,
+        left_open: bool,
+        right_open: bool,
+    ) -> M::Summary {
+        if x.is_null() {
+            return M::empty();
+        }
+        if left_open && right_open {
+            return self.arena.agg(x, version);
+        }
+
+        let key = self.arena.key(x);
+        let left = self.arena.left(x, version);
+        let right = self.arena.right(x, version);
+        let after_start = left_open
+            || match range.start_bound() {
+                Bound::Included(start) => key >= start,
+                Bound::Excluded(start) => key > start,
+                Bound::Unbounded => true,
+            };
+        let before_end = right_open
+            || match range.end_bound() {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+        match (after_start, before_end) {
+            (false, _) => self.aggregate_below(right, version, range, left_open, right_open),
+            (_, false) => self.aggregate_below(left, version, range, left_open, right_open),
+            (true, true) => {
+                let own = M::lift(key, self.arena.value_at(x, version));
+                M::combine(
+                    &M::combine(
+                        &self.aggregate_below(left, version, range, left_open, true),
+                        &own,
+                    ),
+                    &self.aggregate_below(right, version, range, true, right_open),
+                )
+            }
+        }
+    }
+
+    // First node whose key is not before `start`
+    fn lower_bound(&self, root: NodePtr, start: Bound<&K>, version: usize) -> NodePtr {
+        let mut x = root;
+        let mut candidate = NodePtr::null();
+        while !x.is_null() {
+            let key = self.arena.key(x);
+            let after_start = match start {
+                Bound::Included(start) => key >= start,
+                Bound::Excluded(start) => key > start,
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                candidate = x;
+                x = self.arena.left(x, version);
+            } else {
+                x = self.arena.right(x, version);
+            }
+        }
+        candidate
+    }
+
+    /// Checks every invariant of the tree seen from `version`: keys in order,
+    /// red-black properties, parent and child links that agree, subtree sizes
+    /// and stamps, fat nodes within bounds and the length recorded for it.
+    pub fn validate(&self, version: usize) -> Result<(), GojoError> {
+        let (root, len, _) = self
+            .root_entry(version)
+            .map_err(|err| err.downcast().unwrap_or(GojoError::Unknown))?;
+        let broken =
+            |what: String| GojoError::BrokenInvariant(format!("version {version}, {what}"));
+        self.arena.validate_copies(version).map_err(broken)?;
+        if root.is_null() {
+            return match len {
+                0 => Ok(()),
+                _ => Err(broken(format!("empty tree with len {len}"))),
+            };
+        }
+
+        let root = self.arena.get_last_copy(root, version);
+        if !self.arena.parent(root, version).is_null() {
+            return Err(broken(format!("root {root:?}: has a parent")));
+        }
+        if self.arena.is_red_color(root, version) {
+            return Err(broken(format!("root {root:?}: is red")));
+        }
+        let mut nodes = 0;
+        self.validate_subtree(root, version, None, None, &mut nodes)
+            .map_err(broken)?;
+        if nodes != len {
+            return Err(broken(format!("{nodes} nodes with len {len}")));
+        }
+        Ok(())
+    }
+
+    /// Checks every version still in history, see `validate`.
+    pub fn validate_all(&self) -> Result<(), GojoError> {
+        (self.first_version..=self.curr_version).try_for_each(|version| self.validate(version))
+    }
+
+    // Checks the subtree of `node`, whose keys must all be between `low` and
+    // `high`, and returns its black height
+    fn validate_subtree(
+        &self,
+        node: NodePtr,
+        version: usize,
+        low: Option<&K>,
+        high: Option<&K>,
+        nodes: &mut usize,
+    ) -> Result<usize, String> {
+        if node.is_null() {
+            return Ok(1);
+        }
+        *nodes += 1;
+        let arena = &self.arena;
+        let node = arena.get_last_copy(node, version);
+        let key = arena.key(node);
+        if low.is_some_and(|low| key <= low) || high.is_some_and(|high| key >= high) {
+            return Err(format!("node {node:?}: key out of order"));
+        }
+        if arena.count(node, version) == 0 {
+            return Err(format!("node {node:?}: no copies of its key"));
+        }
+        if arena.stamp(node, version) > version {
+            return Err(format!("node {node:?}: stamped by a later version"));
+        }
+
+        let left = arena.left(node, version);
+        let right = arena.right(node, version);
+        for child in [left, right].into_iter().filter(|child| !child.is_null()) {
+            if arena.parent(child, version) != node {
+                return Err(format!("node {child:?}: parent is not {node:?}"));
+            }
+            if arena.is_red_color(node, version) && arena.is_red_color(child, version) {
+                return Err(format!("node {child:?}: red child of red {node:?}"));
+            }
+            if arena.stamp(child, version) > arena.stamp(node, version) {
+                return Err(format!("node {child:?}: stamped after its parent"));
+            }
+        }
+        if arena.size(node, version) != arena.size(left, version) + arena.size(right, version) + 1 {
+            return Err(format!("node {node:?}: size out of sync"));
+        }
+
+        let left_height = self.validate_subtree(left, version, low, Some(key), nodes)?;
+        let right_height = self.validate_subtree(right, version, Some(key), high, nodes)?;
+        if left_height != right_height {
+            return Err(format!("node {node:?}: uneven black heights"));
         }
-        let root = self.roots[version].0;
-        let len = self.roots[version].1;
-        let head = Some(NodeInfo::first_child(root, version));
-        let tail = Some(NodeInfo::last_child(root, version));
+        Ok(left_height + usize::from(arena.is_black_color(node, version)))
+    }
+
+    /// Keys added, removed and changed from version `old` to version `new`,
+    /// in key order. Any two versions can be compared, not only one and a
+    /// version derived from it.
+    pub fn diff(&self, old: usize, new: usize) -> Result<GojoDiff<'_, K, V, MODS, M>>
+    where
+        V: PartialEq,
+    {
+        let pending = |root: NodePtr| match root.is_null() {
+            true => Vec::new(),
+            false => vec![Pending::Subtree(root)],
+        };
+        let (old_root, _, _) = self.root_entry(old)?;
+        let (new_root, _, _) = self.root_entry(new)?;
+        Ok(GojoDiff {
+            arena: &self.arena,
+            old: pending(old_root),
+            new: pending(new_root),
+            old_version: old,
+            new_version: new,
+        })
+    }
+
+    pub fn arena_stats(&self) -> ArenaStats {
+        let nodes = &self.arena.nodes;
+        ArenaStats {
+            nodes: nodes.len(),
+            copies: nodes
+                .iter()
+                .filter(|node| !node.next_copy.is_null())
+                .count(),
+            bytes: nodes.capacity() * mem::size_of::<GojoNode<K, V, MODS, M>>(),
+        }
+    }
+
+    pub fn node_info_iter(&self, version: usize) -> Result<NodeInfoIter<'_, K, V, MODS, M>> {
+        let (root, len, _) = self.root_entry(version)?;
+        let head = NodeInfo::first_child(&self.arena, root, version);
+        let tail = NodeInfo::last_child(&self.arena, root, version);
 
         Ok(NodeInfoIter {
+            arena: &self.arena,
             head,
             tail,
             len,
             version,
-            _marker: marker::PhantomData,
         })
     }
 }
 
 #[cfg(test)]
 mod tree_tests {
+    use arrayvec::ArrayVec;
     use pretty_assertions::assert_eq;
 
-    use crate::gojo::{Color, Mod, ModData, NodeInfo, NodePtr};
+    use crate::gojo::{monoid::Sum, Color, Mod, ModData, NodeInfo, NodePtr};
 
-    use super::{Gojo, GojoNode};
+    use super::{
+        Arena, ArenaStats, DiffEvent, DuplicatePolicy, Gojo, GojoError, GojoNode, InsertOutcome,
+        VersionList, SHORT_CHAIN,
+    };
     use anyhow::Result;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::num::NonZeroU64;
+    use std::ops::Bound;
+
+    fn linear_arena(latest: usize) -> Arena<i32, i32, 6> {
+        let mut versions = VersionList::new(latest + 1);
+        for version in 0..latest {
+            versions.insert_after(version);
+        }
+        Arena::new(versions)
+    }
 
     #[test]
     fn test_get_color_without_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let no_mods_node = GojoNode {
             color: Color::Black,
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(no_mods_node);
+        let ptr = arena.alloc(no_mods_node);
         let expect_color = Color::Black;
         let version = 1;
 
         // Act
-        let actual_color = ptr.get_color(version);
+        let actual_color = arena.get_color(ptr, version);
 
         // Assert
         assert_eq!(expect_color, actual_color);
@@ -1436,23 +3460,24 @@ mod tree_tests {
     #[test]
     fn test_get_color_with_five_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let five_mod_node = GojoNode {
             color: Color::Black,
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Col(Color::Red), 2),
                 Mod::new(ModData::Col(Color::Black), 3),
                 Mod::new(ModData::Col(Color::Red), 4),
                 Mod::new(ModData::Col(Color::Black), 5),
                 Mod::new(ModData::Col(Color::Red), 6),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(five_mod_node);
+        let ptr = arena.alloc(five_mod_node);
         let expected_color = Color::Red;
         let version = 6;
 
         // Act
-        let actual_color = ptr.get_color(version);
+        let actual_color = arena.get_color(ptr, version);
 
         // Assert
         assert_eq!(expected_color, actual_color);
@@ -1461,24 +3486,25 @@ mod tree_tests {
     #[test]
     fn test_get_color_with_bursted_node() {
         // Arrange
+        let mut arena = linear_arena(7);
         let version = 7;
         let bursted_node = GojoNode {
             version: 1,
             color: Color::Black,
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Col(Color::Red), 2),
                 Mod::new(ModData::Col(Color::Black), 3),
                 Mod::new(ModData::Col(Color::Red), 4),
                 Mod::new(ModData::Col(Color::Black), 5),
                 Mod::new(ModData::Col(Color::Red), 6),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let mut bursted_node_ptr = NodePtr::<i32, i32>::from(bursted_node);
+        let bursted_node_ptr = arena.alloc(bursted_node);
 
         // Act
-        bursted_node_ptr.set_color(Color::Black, version);
-        let actual_color = bursted_node_ptr.get_color(version);
+        arena.set_color(bursted_node_ptr, Color::Black, version);
+        let actual_color = arena.get_color(bursted_node_ptr, version);
 
         // Assert
         assert_eq!(actual_color, Color::Black);
@@ -1487,14 +3513,15 @@ mod tree_tests {
     #[test]
     fn test_get_left_without_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let no_mods_node = GojoNode {
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(no_mods_node);
+        let ptr = arena.alloc(no_mods_node);
         let version = 1;
 
         // Act
-        let actual_left = ptr.left(version);
+        let actual_left = arena.left(ptr, version);
 
         // Assert
         assert!(actual_left.is_null());
@@ -1503,24 +3530,25 @@ mod tree_tests {
     #[test]
     fn test_get_left_with_five_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let left = GojoNode {
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let expected_left = NodePtr::<i32, i32>::from(left);
+        let expected_left = arena.alloc(left);
         let five_mods_node = GojoNode {
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Left(NodePtr::null()), 2),
                 Mod::new(ModData::Left(NodePtr::null()), 3),
                 Mod::new(ModData::Left(NodePtr::null()), 4),
                 Mod::new(ModData::Left(expected_left), 5),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(five_mods_node);
+        let ptr = arena.alloc(five_mods_node);
         let version = 5;
 
         // Act
-        let actual_left = ptr.left(version);
+        let actual_left = arena.left(ptr, version);
 
         // Assert
         assert_eq!(expected_left, actual_left);
@@ -1529,13 +3557,14 @@ mod tree_tests {
     #[test]
     fn test_get_left_with_bursted_node() {
         // Arrange
+        let mut arena = linear_arena(7);
         let left_node = GojoNode {
             version: 7,
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let expected_left_ptr = NodePtr::<i32, i32>::from(left_node);
+        let expected_left_ptr = arena.alloc(left_node);
         let bursted_node = GojoNode {
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Left(NodePtr::null()), 2),
                 Mod::new(ModData::Left(NodePtr::null()), 3),
                 Mod::new(ModData::Left(NodePtr::null()), 4),
@@ -1543,14 +3572,14 @@ mod tree_tests {
                 Mod::new(ModData::Left(NodePtr::null()), 5),
                 Mod::new(ModData::Left(NodePtr::null()), 6),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let mut bursted_node_ptr = NodePtr::<i32, i32>::from(bursted_node);
+        let bursted_node_ptr = arena.alloc(bursted_node);
         let version = 7;
 
         // Act
-        bursted_node_ptr.set_left(expected_left_ptr, version);
-        let actual_left = bursted_node_ptr.left(version);
+        arena.set_left(bursted_node_ptr, expected_left_ptr, version);
+        let actual_left = arena.left(bursted_node_ptr, version);
 
         // Assert
         assert_eq!(expected_left_ptr, actual_left);
@@ -1559,14 +3588,15 @@ mod tree_tests {
     #[test]
     fn test_get_right_without_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let no_mods_node = GojoNode {
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(no_mods_node);
+        let ptr = arena.alloc(no_mods_node);
         let version = 1;
 
         // Act
-        let actual_right = ptr.right(version);
+        let actual_right = arena.right(ptr, version);
 
         // Assert
         assert!(actual_right.is_null());
@@ -1575,24 +3605,25 @@ mod tree_tests {
     #[test]
     fn test_get_right_with_five_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let right = GojoNode {
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let expected_right = NodePtr::<i32, i32>::from(right);
+        let expected_right = arena.alloc(right);
         let five_mods_node = GojoNode {
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Right(NodePtr::null()), 2),
                 Mod::new(ModData::Right(NodePtr::null()), 3),
                 Mod::new(ModData::Right(NodePtr::null()), 4),
                 Mod::new(ModData::Right(expected_right), 5),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(five_mods_node);
+        let ptr = arena.alloc(five_mods_node);
         let version = 5;
 
         // Act
-        let actual_right = ptr.right(version);
+        let actual_right = arena.right(ptr, version);
 
         // Assert
         assert_eq!(expected_right, actual_right);
@@ -1601,13 +3632,14 @@ mod tree_tests {
     #[test]
     fn test_get_right_with_bursted_node() {
         // Arrange
+        let mut arena = linear_arena(7);
         let right_node = GojoNode {
             version: 7,
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let expected_right_ptr = NodePtr::<i32, i32>::from(right_node);
+        let expected_right_ptr = arena.alloc(right_node);
         let bursted_node = GojoNode {
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Right(NodePtr::null()), 2),
                 Mod::new(ModData::Right(NodePtr::null()), 3),
                 Mod::new(ModData::Right(NodePtr::null()), 4),
@@ -1615,14 +3647,14 @@ mod tree_tests {
                 Mod::new(ModData::Right(NodePtr::null()), 5),
                 Mod::new(ModData::Right(NodePtr::null()), 6),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let mut bursted_node_ptr = NodePtr::<i32, i32>::from(bursted_node);
+        let bursted_node_ptr = arena.alloc(bursted_node);
         let version = 7;
 
         // Act
-        bursted_node_ptr.set_right(expected_right_ptr, version);
-        let actual_right = bursted_node_ptr.right(version);
+        arena.set_right(bursted_node_ptr, expected_right_ptr, version);
+        let actual_right = arena.right(bursted_node_ptr, version);
 
         // Assert
         assert_eq!(expected_right_ptr, actual_right);
@@ -1631,14 +3663,15 @@ mod tree_tests {
     #[test]
     fn test_get_parent_without_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let no_mods_node = GojoNode {
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(no_mods_node);
+        let ptr = arena.alloc(no_mods_node);
         let version = 1;
 
         // Act
-        let actual_parent = ptr.parent(version);
+        let actual_parent = arena.parent(ptr, version);
 
         // Assert
         assert!(actual_parent.is_null());
@@ -1647,24 +3680,25 @@ mod tree_tests {
     #[test]
     fn test_get_parent_with_five_mods() {
         // Arrange
+        let mut arena = linear_arena(7);
         let parent = GojoNode {
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let expected_parent = NodePtr::<i32, i32>::from(parent);
+        let expected_parent = arena.alloc(parent);
         let five_mods_node = GojoNode {
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Parent(NodePtr::null()), 2),
                 Mod::new(ModData::Parent(NodePtr::null()), 3),
                 Mod::new(ModData::Parent(NodePtr::null()), 4),
                 Mod::new(ModData::Parent(expected_parent), 5),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let ptr = NodePtr::<i32, i32>::from(five_mods_node);
+        let ptr = arena.alloc(five_mods_node);
         let version = 5;
 
         // Act
-        let actual_parent = ptr.parent(version);
+        let actual_parent = arena.parent(ptr, version);
 
         // Assert
         assert_eq!(expected_parent, actual_parent);
@@ -1673,13 +3707,14 @@ mod tree_tests {
     #[test]
     fn test_get_parent_with_bursted_node() {
         // Arrange
+        let mut arena = linear_arena(7);
         let parent_node = GojoNode {
             version: 7,
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let mut expected_parent_ptr = NodePtr::<i32, i32>::from(parent_node);
+        let expected_parent_ptr = arena.alloc(parent_node);
         let bursted_node = GojoNode {
-            mods: Vec::from([
+            mods: ArrayVec::from_iter([
                 Mod::new(ModData::Parent(NodePtr::null()), 2),
                 Mod::new(ModData::Parent(NodePtr::null()), 3),
                 Mod::new(ModData::Parent(NodePtr::null()), 4),
@@ -1687,15 +3722,15 @@ mod tree_tests {
                 Mod::new(ModData::Parent(NodePtr::null()), 5),
                 Mod::new(ModData::Parent(NodePtr::null()), 6),
             ]),
-            ..Default::default()
+            ..GojoNode::new(0, 0)
         };
-        let mut bursted_node_ptr = NodePtr::<i32, i32>::from(bursted_node);
+        let bursted_node_ptr = arena.alloc(bursted_node);
         let version = 7;
 
         // Act
-        expected_parent_ptr.set_left(bursted_node_ptr, version);
-        bursted_node_ptr.set_parent(expected_parent_ptr, version);
-        let actual_parent = bursted_node_ptr.parent(version);
+        arena.set_left(expected_parent_ptr, bursted_node_ptr, version);
+        arena.set_parent(bursted_node_ptr, expected_parent_ptr, version);
+        let actual_parent = arena.parent(bursted_node_ptr, version);
 
         // Assert
         assert_eq!(expected_parent_ptr, actual_parent);
@@ -1705,7 +3740,7 @@ mod tree_tests {
     fn test_bursted_root_is_setted_as_new_root() {
         // Arrange
         let mut gojo = Gojo::<i32, i32>::default();
-        let version = 7;
+        let version = 8;
 
         // Act
         gojo.insert(5, 5);
@@ -1715,11 +3750,12 @@ mod tree_tests {
         gojo.insert(2, 2);
         gojo.insert(6, 6);
         gojo.insert(7, 7);
+        gojo.insert(4, 4);
 
         // Assert
-        assert_eq!(gojo.root.get_value(), 5);
-        assert_eq!(gojo.root.get_color(version), Color::Black);
-        assert_eq!(gojo.root.version(), 7);
+        assert_eq!(gojo.arena.get_value(gojo.root), 5);
+        assert_eq!(gojo.arena.get_color(gojo.root, version), Color::Black);
+        assert_eq!(gojo.arena.version(gojo.root), 8);
     }
 
     #[test]
@@ -1748,14 +3784,12 @@ mod tree_tests {
         }
 
         // Assert
-        assert_eq!(unsafe { (*m.root.pointer).key }, 4);
+        assert_eq!(*m.arena.key(m.root), 4);
         for (key, color) in expected.iter() {
             let ptr = m.find_node(key, maximum);
             assert!(!ptr.is_null());
-            unsafe {
-                assert_eq!((*ptr.pointer).key, *key);
-                assert_eq!(ptr.get_color(maximum), *color);
-            }
+            assert_eq!(m.arena.key(ptr), key);
+            assert_eq!(m.arena.get_color(ptr, maximum), *color);
         }
     }
 
@@ -1771,7 +3805,7 @@ mod tree_tests {
         }
 
         // Assert
-        assert_eq!(unsafe { (*m.root.pointer).key }, 7);
+        assert_eq!(*m.arena.key(m.root), 7);
         let expected = [
             (1, Color::Red),
             (2, Color::Black),
@@ -1787,10 +3821,8 @@ mod tree_tests {
         for (key, color) in expected.iter() {
             let ptr = m.find_node(key, maximum);
             assert!(!ptr.is_null());
-            unsafe {
-                assert_eq!((*ptr.pointer).key, *key);
-                assert_eq!(ptr.get_color(maximum), *color);
-            }
+            assert_eq!(m.arena.key(ptr), key);
+            assert_eq!(m.arena.get_color(ptr, maximum), *color);
         }
     }
 
@@ -2029,6 +4061,26 @@ mod tree_tests {
         assert_eq!(&1, version_tree_succ.unwrap());
     }
 
+    #[test]
+    fn test_predecessor_is_strict_and_works_on_missing_keys() {
+        // Arrange
+        let mut gojo: Gojo<usize, i32> = Gojo::default();
+        for key in [10, 20, 30] {
+            gojo.insert(key, key as i32);
+        }
+
+        // Act
+        let present = gojo.predecessor(&20, 3);
+        let missing = gojo.predecessor(&25, 3);
+        let floor = gojo.floor(&20, 3);
+
+        // Assert
+        assert_eq!(Some(&10), present);
+        assert_eq!(Some(&20), missing);
+        assert_eq!(Some((&20, &20)), floor);
+        assert_eq!(None, gojo.predecessor(&10, 3));
+    }
+
     #[test]
     fn test_multiple_inserts_and_deletions() {
         // Arrange
@@ -2069,6 +4121,72 @@ mod tree_tests {
         }
     }
 
+    #[test]
+    fn test_arena_owns_copies_of_every_version() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+
+        // Act
+        for key in 1..=50 {
+            gojo.insert(key, key);
+        }
+        for key in (1..=50).step_by(3) {
+            gojo.remove(&key);
+        }
+
+        // Assert
+        assert!(gojo.arena.nodes.len() > 50);
+        let copies = gojo
+            .arena
+            .nodes
+            .iter()
+            .filter(|node| !node.next_copy.is_null())
+            .count();
+        assert!(copies > 0);
+    }
+
+    #[test]
+    fn test_clear_frees_every_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=20 {
+            gojo.insert(key, key);
+        }
+        gojo.remove(&7);
+
+        // Act
+        gojo.clear();
+        gojo.insert(3, 3);
+
+        // Assert
+        assert_eq!(1, gojo.arena.nodes.len());
+        assert_eq!(1, gojo.latest_version());
+        assert_eq!(Some(&3), gojo.get(&3, 1));
+        assert_eq!(None, gojo.get(&7, 1));
+        assert_eq!(0, gojo.iter(0)?.count());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fast_clear_keeps_capacity() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=20 {
+            gojo.insert(key, key);
+        }
+        let capacity = gojo.arena.nodes.capacity();
+
+        // Act
+        gojo.fast_clear();
+        gojo.insert(3, 3);
+
+        // Assert
+        assert_eq!(capacity, gojo.arena.nodes.capacity());
+        assert_eq!(1, gojo.latest_version());
+        assert_eq!(Some(&3), gojo.get(&3, 1));
+    }
+
     #[test]
     #[ignore]
     fn test_college_remove_that_changes_version_when_element_not_found() {
@@ -2156,4 +4274,1677 @@ mod tree_tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_nearest_keys_to_absent_key() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in [10, 20, 30, 40, 50] {
+            gojo.insert(key, key << 1);
+        }
+        gojo.remove(&30);
+
+        // Act
+        let floor = gojo.floor(&30, 6);
+        let ceiling = gojo.ceiling(&30, 6);
+        let strict_predecessor = gojo.strict_predecessor(&30, 6);
+        let strict_successor = gojo.strict_successor(&30, 6);
+
+        // Assert
+        assert_eq!(Some((&20, &40)), floor);
+        assert_eq!(Some((&40, &80)), ceiling);
+        assert_eq!(Some((&20, &40)), strict_predecessor);
+        assert_eq!(Some((&40, &80)), strict_successor);
+        assert_eq!(Some((&30, &60)), gojo.floor(&30, 5));
+        assert_eq!(Some((&30, &60)), gojo.ceiling(&30, 5));
+        assert_eq!(Some((&20, &40)), gojo.strict_predecessor(&30, 5));
+        assert_eq!(Some((&40, &80)), gojo.strict_successor(&30, 5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nearest_keys_out_of_bounds() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in [10, 20, 30] {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        let below_all = gojo.floor(&5, 3);
+        let above_all = gojo.ceiling(&35, 3);
+
+        // Assert
+        assert_eq!(None, below_all);
+        assert_eq!(None, above_all);
+        assert_eq!(None, gojo.strict_predecessor(&10, 3));
+        assert_eq!(None, gojo.strict_successor(&30, 3));
+        assert_eq!(None, gojo.floor(&20, 0));
+        assert_eq!(None, gojo.ceiling(&20, 77));
+        assert_eq!(Some((&10, &10)), gojo.ceiling(&5, 1));
+    }
+
+    #[test]
+    fn test_nearest_keys_match_btree_set() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        let mut snapshots = vec![BTreeSet::new()];
+        for _ in 0..300 {
+            let key = rng.gen_range(0..80) * 2;
+            let mut snapshot = snapshots.last().unwrap().clone();
+            match rng.gen_bool(0.3) {
+                true => {
+                    if gojo.remove(&key).is_some() {
+                        snapshot.remove(&key);
+                        snapshots.push(snapshot);
+                    }
+                }
+                false if !snapshot.contains(&key) => {
+                    gojo.insert(key, key);
+                    snapshot.insert(key);
+                    snapshots.push(snapshot);
+                }
+                false => (),
+            }
+        }
+
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            for k in -1..=161 {
+                // Act
+                let floor = gojo.floor(&k, version).map(|(k, _)| *k);
+                let ceiling = gojo.ceiling(&k, version).map(|(k, _)| *k);
+                let strict_predecessor = gojo.strict_predecessor(&k, version).map(|(k, _)| *k);
+                let strict_successor = gojo.strict_successor(&k, version).map(|(k, _)| *k);
+
+                // Assert
+                assert_eq!(snapshot.range(..=k).next_back().copied(), floor);
+                assert_eq!(snapshot.range(k..).next().copied(), ceiling);
+                assert_eq!(snapshot.range(..k).next_back().copied(), strict_predecessor);
+                assert_eq!(snapshot.range(k + 1..).next().copied(), strict_successor);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_select_and_median() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in [50, 20, 80, 10, 30, 70, 90, 60] {
+            gojo.insert(key, key << 1);
+        }
+        gojo.remove(&20);
+
+        // Act
+        let rank = gojo.rank(&70, 9)?;
+        let selected = gojo.select(2, 9)?;
+        let median = gojo.median(9)?;
+
+        // Assert
+        assert_eq!(Some(4), rank);
+        assert_eq!(Some((&50, &100)), selected);
+        assert_eq!(Some((&60, &120)), median);
+        assert_eq!(Some(5), gojo.rank(&70, 8)?);
+        assert_eq!(Some((&30, &60)), gojo.select(2, 8)?);
+        assert_eq!(Some((&50, &100)), gojo.median(8)?);
+        assert_eq!(None, gojo.rank(&65, 9)?);
+        assert_eq!(None, gojo.rank(&20, 9)?);
+        assert_eq!(None, gojo.select(7, 9)?);
+        assert_eq!(None, gojo.median(0)?);
+        assert_eq!(Some(0), gojo.rank(&10, 9)?);
+        assert!(gojo.rank(&10, 10).is_err());
+        assert!(gojo.select(0, 10).is_err());
+        assert!(gojo.median(10).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_statistics_match_btree_set_on_every_version() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(12);
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        let mut snapshots = vec![BTreeSet::new()];
+        for _ in 0..400 {
+            let key = rng.gen_range(0..100) * 2;
+            let from = rng.gen_range(0..snapshots.len());
+            let mut snapshot: BTreeSet<i32> = snapshots[from].clone();
+            if snapshot.contains(&key) {
+                gojo.remove_at(from, &key)?;
+                snapshot.remove(&key);
+            } else {
+                gojo.insert_at(from, key, key)?;
+                snapshot.insert(key);
+            }
+            snapshots.push(snapshot);
+        }
+
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            let keys: Vec<i32> = snapshot.iter().copied().collect();
+
+            // Act
+            let mut selected = Vec::new();
+            for i in 0..=keys.len() {
+                selected.extend(gojo.select(i, version)?.map(|(k, _)| *k));
+            }
+            let median = gojo.median(version)?.map(|(k, _)| *k);
+
+            // Assert
+            assert_eq!(keys, selected, "for version {version}");
+            assert_eq!(keys.get(keys.len().saturating_sub(1) / 2).copied(), median);
+            for k in [-1, 0, 1, 99, 100, 101, 200] {
+                let expected = snapshot.contains(&k).then(|| snapshot.range(..k).count());
+                assert_eq!(expected, gojo.rank(&k, version)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_policy_keeps_first_value() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::with_policy(10, DuplicatePolicy::Reject);
+        gojo.insert(1, 10);
+
+        // Act
+        let outcome = gojo.insert(1, 20);
+
+        // Assert
+        assert_eq!(InsertOutcome::Rejected, outcome);
+        assert!(!outcome.created_version());
+        assert_eq!(1, gojo.latest_version());
+        assert_eq!(Some(&10), gojo.get(&1, 1));
+        assert_eq!(Some(1), gojo.len(1));
+    }
+
+    #[test]
+    fn test_default_policy_makes_a_version_for_every_insert() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        gojo.insert(1, 10);
+
+        // Act
+        let outcome = gojo.insert(1, 20);
+
+        // Assert
+        assert_eq!(DuplicatePolicy::Multi, gojo.policy());
+        assert_eq!(InsertOutcome::Counted, outcome);
+        assert_eq!(2, gojo.latest_version());
+        assert_eq!(2, gojo.count(&1, 2));
+        assert_eq!(Some(&10), gojo.get(&1, 2));
+    }
+
+    #[test]
+    fn test_replace_policy_records_new_value() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::with_policy(10, DuplicatePolicy::Replace);
+        gojo.insert(1, 10);
+        gojo.insert(2, 20);
+
+        // Act
+        let outcome = gojo.insert(1, 11);
+        let outcome_at = gojo.insert_at(1, 1, 12)?;
+
+        // Assert
+        assert_eq!(InsertOutcome::Replaced, outcome);
+        assert_eq!(InsertOutcome::Replaced, outcome_at);
+        assert_eq!(4, gojo.latest_version());
+        assert_eq!(Some(&10), gojo.get(&1, 2));
+        assert_eq!(Some(&11), gojo.get(&1, 3));
+        assert_eq!(Some(&12), gojo.get(&1, 4));
+        assert_eq!(Some(2), gojo.len(3));
+        assert_eq!(
+            vec![(&1, &11), (&2, &20)],
+            gojo.iter(3)?.collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_policy_counts_copies() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::with_policy(10, DuplicatePolicy::Multi);
+        gojo.insert(1, 10);
+        gojo.insert(2, 20);
+
+        // Act
+        let first = gojo.insert(1, 11);
+        let second = gojo.insert(1, 12);
+        let removed = gojo.remove(&1);
+
+        // Assert
+        assert_eq!(InsertOutcome::Counted, first);
+        assert_eq!(InsertOutcome::Counted, second);
+        assert_eq!(Some(10), removed);
+        assert_eq!(5, gojo.latest_version());
+        assert_eq!(1, gojo.count(&1, 2));
+        assert_eq!(2, gojo.count(&1, 3));
+        assert_eq!(3, gojo.count(&1, 4));
+        assert_eq!(2, gojo.count(&1, 5));
+        assert_eq!(Some(&10), gojo.get(&1, 5));
+        assert_eq!(Some(2), gojo.len(5));
+        assert_eq!(Some(10), gojo.remove(&1));
+        assert_eq!(Some(10), gojo.remove(&1));
+        assert_eq!(0, gojo.count(&1, 7));
+        assert_eq!(vec![(&2, &20)], gojo.iter(7)?.collect::<Vec<_>>());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_survives_clear_and_snapshot() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::with_policy(10, DuplicatePolicy::Multi);
+        gojo.insert(1, 1);
+
+        // Act
+        let snapshot = gojo.snapshot(1)?;
+        gojo.clear();
+
+        // Assert
+        assert_eq!(DuplicatePolicy::Multi, snapshot.policy());
+        assert_eq!(DuplicatePolicy::Multi, gojo.policy());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_by_label() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=5 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        gojo.label_version(3, "release-3")?;
+        gojo.label_version(5, "latest")?;
+        gojo.label_version(3, "release-3")?;
+
+        // Assert
+        assert_eq!(Some(3), gojo.version_by_label("release-3"));
+        assert_eq!(Some(5), gojo.version_by_label("latest"));
+        assert_eq!(None, gojo.version_by_label("release-4"));
+        let in_use = gojo.label_version(4, "release-3").err().unwrap();
+        assert!(matches!(
+            in_use.downcast_ref::<GojoError>(),
+            Some(GojoError::LabelInUse(_))
+        ));
+        assert!(gojo.label_version(6, "future").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_at_or_before_timestamp() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=6 {
+            gojo.insert(key, key);
+            gojo.stamp_version(key, key as u64 * 100)?;
+        }
+        gojo.insert_at(2, 42, 42)?;
+
+        // Act
+        let version = gojo.version_at_or_before(350);
+
+        // Assert
+        assert_eq!(Some(3), version);
+        assert_eq!(None, gojo.version_at_or_before(99));
+        assert_eq!(Some(1), gojo.version_at_or_before(100));
+        assert_eq!(Some(6), gojo.version_at_or_before(u64::MAX));
+        assert_eq!(Some(300), gojo.timestamp(3));
+        assert_eq!(None, gojo.timestamp(7));
+        gojo.stamp_version(7, 600)?;
+        assert_eq!(Some(7), gojo.version_at_or_before(650));
+        let out_of_order = gojo.stamp_version(4, 250).err().unwrap();
+        assert!(matches!(
+            out_of_order.downcast_ref::<GojoError>(),
+            Some(GojoError::TimestampOutOfOrder(_))
+        ));
+        gojo.stamp_version(4, 350)?;
+        assert_eq!(Some(4), gojo.version_at_or_before(350));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_history_drops_pruned_metadata() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=6 {
+            gojo.insert(key, key);
+            gojo.stamp_version(key, key as u64)?;
+        }
+        gojo.label_version(2, "old")?;
+        gojo.label_version(5, "new")?;
+
+        // Act
+        gojo.truncate_history(4)?;
+
+        // Assert
+        assert_eq!(None, gojo.version_by_label("old"));
+        assert_eq!(Some(5), gojo.version_by_label("new"));
+        assert_eq!(None, gojo.version_at_or_before(3));
+        assert_eq!(Some(4), gojo.version_at_or_before(4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_between_versions() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::with_policy(10, DuplicatePolicy::Replace);
+        for key in 1..=8 {
+            gojo.insert(key, key);
+        }
+        gojo.remove(&3);
+        gojo.insert(5, 50);
+        gojo.insert(10, 10);
+
+        // Act
+        let events: Vec<_> = gojo.diff(8, 11)?.collect();
+
+        // Assert
+        assert_eq!(
+            vec![
+                DiffEvent::Removed(&3, &3),
+                DiffEvent::Changed {
+                    key: &5,
+                    old: &5,
+                    new: &50
+                },
+                DiffEvent::Added(&10, &10),
+            ],
+            events
+        );
+        assert_eq!(0, gojo.diff(11, 11)?.count());
+        assert_eq!(8, gojo.diff(0, 8)?.count());
+        assert!(gojo.diff(0, 12).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_between_branches_matches_btree_map() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(15);
+        let mut gojo: Gojo<i32, i32> = Gojo::with_policy(10, DuplicatePolicy::Replace);
+        let mut snapshots = vec![BTreeMap::new()];
+        for _ in 0..300 {
+            let key = rng.gen_range(0..60);
+            let from = rng.gen_range(snapshots.len().saturating_sub(20)..snapshots.len());
+            let mut snapshot: BTreeMap<i32, i32> = snapshots[from].clone();
+            if snapshot.contains_key(&key) && rng.gen_bool(0.5) {
+                gojo.remove_at(from, &key)?;
+                snapshot.remove(&key);
+            } else {
+                let value = rng.gen_range(0..3);
+                gojo.insert_at(from, key, value)?;
+                snapshot.insert(key, value);
+            }
+            snapshots.push(snapshot);
+        }
+
+        for _ in 0..200 {
+            let old = rng.gen_range(0..snapshots.len());
+            let new = rng.gen_range(old.saturating_sub(30)..snapshots.len());
+
+            // Act
+            let events: Vec<_> = gojo.diff(old, new)?.collect();
+
+            // Assert
+            let (before, after) = (&snapshots[old], &snapshots[new]);
+            let keys: BTreeSet<&i32> = before.keys().chain(after.keys()).collect();
+            let expected: Vec<_> = keys
+                .into_iter()
+                .filter_map(|k| match (before.get(k), after.get(k)) {
+                    (Some(v), None) => Some(DiffEvent::Removed(k, v)),
+                    (None, Some(v)) => Some(DiffEvent::Added(k, v)),
+                    (Some(old), Some(new)) if old != new => {
+                        Some(DiffEvent::Changed { key: k, old, new })
+                    }
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(expected, events, "from {old} to {new}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_skips_shared_subtrees() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::with_policy(10, DuplicatePolicy::Replace);
+        for key in 0..4096 {
+            gojo.insert(key, key);
+        }
+        let version = gojo.latest_version();
+        gojo.insert(1000, 0);
+
+        // Act
+        let mut diff = gojo.diff(version, version + 1)?;
+        let mut steps = 0;
+        let mut events = 0;
+        while let Some(event) = diff.step() {
+            steps += 1;
+            events += usize::from(event.is_some());
+        }
+
+        // Assert
+        assert!(steps < 200, "took {steps} steps");
+        assert_eq!(1, events);
+
+        Ok(())
+    }
+
+    // Newest copy of `ptr` owning the version, walking the whole chain
+    fn walk_copies<const MODS: usize>(
+        arena: &Arena<i32, i32, MODS>,
+        ptr: NodePtr,
+        version: usize,
+    ) -> NodePtr {
+        let mut last = ptr;
+        let mut caba = arena.get_next_copy(ptr);
+        while !caba.is_null() {
+            if arena.covers(caba, version) {
+                last = caba;
+            }
+            caba = arena.get_next_copy(caba);
+        }
+        last
+    }
+
+    #[test]
+    fn test_copy_lookup_does_not_walk_the_whole_chain() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32, 2> = Gojo::with_mods(10, DuplicatePolicy::Replace);
+        for key in 0..2000 {
+            gojo.insert(key, key);
+        }
+        let chain = gojo
+            .arena
+            .chains
+            .iter()
+            .max_by_key(|chain| chain.copies.len())
+            .unwrap();
+        let bound = SHORT_CHAIN + 2 * chain.copies.len().ilog2() as usize + 2;
+
+        // Act
+        let lookups: Vec<(usize, NodePtr, usize)> = (0..=gojo.latest_version())
+            .map(|version| {
+                let (copy, looked) = gojo.arena.find_copy(chain.head, version);
+                (version, copy, looked)
+            })
+            .collect();
+
+        // Assert
+        assert!(chain.copies.len() > 1000, "{} copies", chain.copies.len());
+        for (version, copy, looked) in lookups {
+            assert_eq!(walk_copies(&gojo.arena, chain.head, version), copy);
+            assert!(
+                looked <= bound,
+                "looked at {looked} copies for version {version}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_lookup_matches_walk_on_random_branches() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut gojo: Gojo<i32, i32, 2> = Gojo::with_mods(10, DuplicatePolicy::Reject);
+        for _ in 0..300 {
+            let from =
+                rng.gen_range(gojo.latest_version().saturating_sub(10)..=gojo.latest_version());
+            let key = rng.gen_range(0..40);
+            if gojo.remove_at(from, &key)?.is_none() {
+                gojo.insert_at(from, key, key)?;
+            }
+        }
+
+        // Act
+        let mut mismatches = Vec::new();
+        for chain in gojo.arena.chains.iter() {
+            for version in 0..=gojo.latest_version() {
+                let walked = walk_copies(&gojo.arena, chain.head, version);
+                if gojo.arena.get_last_copy(chain.head, version) != walked {
+                    mismatches.push((chain.head, version));
+                }
+            }
+        }
+
+        // Assert
+        assert!(gojo.arena.chains.len() > 10);
+        assert_eq!(Vec::<(NodePtr, usize)>::new(), mismatches);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_branches_from_one_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::with_policy(2000, DuplicatePolicy::Replace);
+        for key in 0..25 {
+            gojo.insert(key, key);
+        }
+        let copies_before = gojo.arena_stats().copies;
+
+        // Act
+        let mut branches = Vec::new();
+        for key in 1000..3000 {
+            gojo.insert_at(25, key, key)?;
+            branches.push((gojo.latest_version(), key));
+        }
+
+        // Assert
+        let copies = gojo.arena_stats().copies - copies_before;
+        assert!(copies <= 26 * branches.len(), "{copies} copies");
+        let bound = SHORT_CHAIN + 2 * branches.len().ilog2() as usize + 2;
+        let mut mismatches = Vec::new();
+        for chain in gojo.arena.chains.iter() {
+            for &(version, _) in branches.iter().step_by(97) {
+                let (copy, looked) = gojo.arena.find_copy(chain.head, version);
+                if copy != walk_copies(&gojo.arena, chain.head, version) || looked > bound {
+                    mismatches.push((chain.head, version));
+                }
+            }
+        }
+        assert_eq!(Vec::<(NodePtr, usize)>::new(), mismatches);
+        for (version, key) in branches {
+            assert_eq!(Some(26), gojo.len(version));
+            assert_eq!(Some(&key), gojo.get(&key, version));
+            assert_eq!(None, gojo.get(&(key - 1), version));
+        }
+        gojo.validate(gojo.latest_version())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_random_branches_with_every_policy() -> Result<()> {
+        for policy in [
+            DuplicatePolicy::Reject,
+            DuplicatePolicy::Replace,
+            DuplicatePolicy::Multi,
+        ] {
+            // Arrange
+            let mut rng = StdRng::seed_from_u64(16);
+            let mut gojo: Gojo<i32, i32> = Gojo::with_policy(10, policy);
+            for _ in 0..400 {
+                let key = rng.gen_range(0..80);
+                let from =
+                    rng.gen_range(gojo.latest_version().saturating_sub(20)..=gojo.latest_version());
+                match rng.gen_bool(0.3) {
+                    true => {
+                        gojo.remove_at(from, &key)?;
+                    }
+                    false => {
+                        gojo.insert_at(from, key, key)?;
+                    }
+                }
+            }
+
+            // Act
+            let before_truncate = gojo.validate_all();
+            gojo.truncate_history(gojo.latest_version() / 2)?;
+            let after_truncate = gojo.validate_all();
+
+            // Assert
+            assert!(before_truncate.is_ok(), "{policy:?}: {before_truncate:?}");
+            assert!(after_truncate.is_ok(), "{policy:?}: {after_truncate:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_unavailable_versions() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        for key in 0..5 {
+            gojo.insert(key, key);
+        }
+        gojo.truncate_history(2)?;
+
+        // Act
+        let unknown = gojo.validate(6);
+        let pruned = gojo.validate(1);
+
+        // Assert
+        assert!(matches!(unknown, Err(GojoError::UnknownVersion(_))));
+        assert!(matches!(pruned, Err(GojoError::VersionPruned(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_detects_broken_trees() {
+        // Arrange
+        let build = || {
+            let mut gojo: Gojo<i32, i32> = Gojo::default();
+            for key in 0..20 {
+                gojo.insert(key, key);
+            }
+            gojo
+        };
+        let is_broken = |gojo: &Gojo<i32, i32>, version: usize| {
+            matches!(gojo.validate(version), Err(GojoError::BrokenInvariant(_)))
+        };
+
+        let mut red_root = build();
+        let version = red_root.latest_version();
+        let root = red_root.get_root(version);
+        red_root.arena.set_red_color(root, version);
+
+        let mut wrong_size = build();
+        let root = wrong_size.get_root(version);
+        wrong_size
+            .arena
+            .set_modification(root, ModData::Size(3), version);
+
+        let mut wrong_key = build();
+        let root = wrong_key.get_root(version);
+        wrong_key.arena.node_mut(root).key = 100;
+
+        let mut wrong_len = build();
+        wrong_len.roots[version].1 = 19;
+
+        // Act
+        let valid = build().validate_all();
+
+        // Assert
+        assert!(valid.is_ok());
+        assert!(is_broken(&red_root, version));
+        assert!(!is_broken(&red_root, version - 1));
+        assert!(is_broken(&wrong_size, version));
+        assert!(is_broken(&wrong_key, version));
+        assert!(is_broken(&wrong_len, version));
+    }
+
+    fn random_branches_with_mods<const MODS: usize>() -> Result<ArenaStats> {
+        let mut gojo: Gojo<i32, i32, MODS> = Gojo::with_mods(10, DuplicatePolicy::Reject);
+        let mut snapshots: Vec<BTreeSet<i32>> = vec![BTreeSet::new()];
+        let mut rng = StdRng::seed_from_u64(17);
+        for _ in 0..200 {
+            let from = rng.gen_range(snapshots.len().saturating_sub(10)..snapshots.len());
+            let key = rng.gen_range(0..40);
+            let mut snapshot = snapshots[from].clone();
+            if snapshot.remove(&key) {
+                gojo.remove_at(from, &key)?;
+            } else {
+                snapshot.insert(key);
+                gojo.insert_at(from, key, key)?;
+            }
+            snapshots.push(snapshot);
+        }
+
+        gojo.validate_all()?;
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            let expected: Vec<i32> = snapshot.iter().copied().collect();
+            let actual: Vec<i32> = gojo.iter(version)?.map(|(k, _)| *k).collect();
+            assert_eq!(expected, actual, "for version {version} with {MODS} mods");
+        }
+        Ok(gojo.arena_stats())
+    }
+
+    #[test]
+    fn test_every_mod_capacity_keeps_history() -> Result<()> {
+        // Act
+        let two = random_branches_with_mods::<2>()?;
+        let three = random_branches_with_mods::<3>()?;
+        let six = random_branches_with_mods::<6>()?;
+        let sixteen = random_branches_with_mods::<16>()?;
+
+        // Assert
+        assert!(two.copies > three.copies);
+        assert!(three.copies > six.copies);
+        assert!(six.copies > sixteen.copies);
+        assert!(sixteen.bytes / sixteen.nodes > six.bytes / six.nodes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_recent_branches_stay_valid() -> Result<()> {
+        for seed in 0..8 {
+            // Arrange
+            let mut gojo: Gojo<i32, i32> = Gojo::default();
+            let mut snapshots: Vec<BTreeSet<i32>> = vec![BTreeSet::new()];
+            let mut rng = StdRng::seed_from_u64(seed);
+
+            for _ in 0..400 {
+                // Act
+                let from = rng.gen_range(snapshots.len().saturating_sub(10)..snapshots.len());
+                let key = rng.gen_range(0..60);
+                let mut snapshot = snapshots[from].clone();
+                if snapshot.remove(&key) {
+                    gojo.remove_at(from, &key)?;
+                } else {
+                    snapshot.insert(key);
+                    gojo.insert_at(from, key, key)?;
+                }
+                snapshots.push(snapshot);
+
+                // Assert
+                let version = snapshots.len() - 1;
+                assert_eq!(version, gojo.latest_version(), "seed {seed}");
+                gojo.validate(version)?;
+                let expected: Vec<&i32> = snapshots[version].iter().collect();
+                let actual: Vec<&i32> = gojo.iter(version)?.map(|(k, _)| k).collect();
+                assert_eq!(expected, actual, "seed {seed}, version {version}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rev_iter() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        gojo.insert(1, 1);
+        gojo.insert(2, 2);
+        gojo.insert(3, 3);
+
+        // Act
+        let mut cache = vec![];
+        for e in gojo.iter(3)?.rev() {
+            cache.push(*e.0);
+        }
+
+        // Assert
+        assert_eq!(Some(3), gojo.len(3));
+        assert_eq!(&cache, &vec![3, 2, 1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rev_iter_on_every_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in [8, 3, 12, 1, 6, 10, 15, 4, 7, 9, 2, 5, 11, 14, 13] {
+            gojo.insert(key, key << 1);
+        }
+        gojo.remove(&6);
+        gojo.remove_at(9, &3)?;
+
+        for version in 0..=gojo.latest_version() {
+            // Act
+            let mut backwards: Vec<_> = gojo.iter(version)?.rev().collect();
+            let infos: Vec<_> = gojo.node_info_iter(version)?.rev().collect();
+
+            // Assert
+            backwards.reverse();
+            let forwards: Vec<_> = gojo.iter(version)?.collect();
+            assert_eq!(forwards, backwards, "for version {version}");
+            let mut expected: Vec<_> = gojo.node_info_iter(version)?.collect();
+            expected.reverse();
+            assert_eq!(expected.len(), infos.len());
+            for (expected, actual) in expected.iter().zip(infos.iter()) {
+                assert_eq!(expected.key, actual.key);
+                assert_eq!(expected.color, actual.color);
+                assert_eq!(expected.depth, actual.depth);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_from_both_ends() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=6 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        let mut iter = gojo.iter(6)?;
+        let first = iter.next();
+        let last = iter.next_back();
+
+        // Assert
+        assert_eq!(Some((&1, &1)), first);
+        assert_eq!(Some((&6, &6)), last);
+        assert_eq!(4, iter.len());
+        let middle: Vec<usize> = iter.by_ref().map(|(k, _)| *k).collect();
+        assert_eq!(vec![2, 3, 4, 5], middle);
+        assert_eq!(None, iter.next());
+        assert_eq!(None, iter.next_back());
+        let mut infos = gojo.node_info_iter(6)?;
+        assert_eq!(6, infos.len());
+        assert_eq!(Some(6), infos.next_back().map(|info| info.key));
+        assert_eq!(Some(1), infos.next().map(|info| info.key));
+        assert_eq!(4, infos.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_at_old_version_keeps_history() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=10 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        gojo.insert_at(3, 42, 42)?;
+
+        // Assert
+        assert_eq!(11, gojo.latest_version());
+        assert_eq!(Some(3), gojo.parent_version(11));
+        assert_eq!(Some(4), gojo.len(11));
+        let keys: Vec<usize> = gojo.iter(11)?.map(|(k, _)| *k).collect();
+        assert_eq!(vec![1, 2, 3, 42], keys);
+        for version in 3..=10 {
+            assert!(gojo.get(&42, version).is_none());
+            let keys: Vec<usize> = gojo.iter(version)?.map(|(k, _)| *k).collect();
+            assert_eq!((1..=version).collect::<Vec<usize>>(), keys);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_at_old_version_keeps_history() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=10 {
+            gojo.insert(key, key << 1);
+        }
+
+        // Act
+        let removed = gojo.remove_at(5, &2)?;
+        let not_found = gojo.remove_at(5, &7)?;
+
+        // Assert
+        assert_eq!(Some(2 << 1), removed);
+        assert_eq!(None, not_found);
+        assert_eq!(11, gojo.latest_version());
+        let keys: Vec<usize> = gojo.iter(11)?.map(|(k, _)| *k).collect();
+        assert_eq!(vec![1, 3, 4, 5], keys);
+        assert_eq!(Some(&(2 << 1)), gojo.get(&2, 10));
+        assert_eq!(Some(10), gojo.len(10));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sibling_branches_are_independent() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=4 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        gojo.insert_at(2, 10, 10)?;
+        let first_branch = gojo.latest_version();
+        gojo.insert_at(2, 20, 20)?;
+        let second_branch = gojo.latest_version();
+        gojo.insert_at(first_branch, 11, 11)?;
+        let first_branch_child = gojo.latest_version();
+        gojo.insert(21, 21);
+
+        // Assert
+        let keys =
+            |version| -> Result<Vec<usize>> { Ok(gojo.iter(version)?.map(|(k, _)| *k).collect()) };
+        assert_eq!(vec![1, 2, 3, 4], keys(4)?);
+        assert_eq!(vec![1, 2, 10], keys(first_branch)?);
+        assert_eq!(vec![1, 2, 20], keys(second_branch)?);
+        assert_eq!(vec![1, 2, 10, 11], keys(first_branch_child)?);
+        assert_eq!(vec![1, 2, 10, 11, 21], keys(gojo.latest_version())?);
+        assert_eq!(
+            Some(first_branch_child),
+            gojo.parent_version(gojo.latest_version())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_branch_from_unknown_version() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        gojo.insert(1, 1);
+
+        // Act
+        let inserted = gojo.insert_at(7, 2, 2);
+        let removed = gojo.remove_at(7, &1);
+
+        // Assert
+        assert!(inserted.is_err());
+        assert!(removed.is_err());
+        assert_eq!(1, gojo.latest_version());
+    }
+
+    #[test]
+    fn test_random_branches_match_naive_snapshots() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        let mut snapshots: Vec<BTreeSet<i32>> = vec![BTreeSet::new()];
+        let mut rng = StdRng::seed_from_u64(0xCABA);
+
+        // Act
+        for _ in 0..600 {
+            let from = rng.gen_range(0..snapshots.len());
+            let key = rng.gen_range(0..80);
+            let mut snapshot = snapshots[from].clone();
+            if snapshot.remove(&key) {
+                gojo.remove_at(from, &key)?;
+            } else {
+                snapshot.insert(key);
+                gojo.insert_at(from, key, key << 1)?;
+            }
+            snapshots.push(snapshot);
+        }
+
+        // Assert
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            let expected: Vec<i32> = snapshot.iter().copied().collect();
+            let actual: Vec<i32> = gojo.iter(version)?.map(|(k, _)| *k).collect();
+            assert_eq!(expected, actual, "for version {version}");
+            assert_eq!(Some(snapshot.len()), gojo.len(version));
+            for key in 0..80 {
+                let expected = snapshot.contains(&key).then_some(key << 1);
+                assert_eq!(expected.as_ref(), gojo.get(&key, version));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+
+        // Act
+        for key in 1..=20 {
+            gojo.insert(key, key << 1);
+        }
+        let inclusive: Vec<(usize, usize)> =
+            gojo.range(20, 5..=8)?.map(|(k, v)| (*k, *v)).collect();
+        let exclusive: Vec<usize> = gojo.range(20, 5..8)?.map(|(k, _)| *k).collect();
+        let unbounded_start: Vec<usize> = gojo.range(20, ..3)?.map(|(k, _)| *k).collect();
+        let unbounded_end: Vec<usize> = gojo.range(20, 18..)?.map(|(k, _)| *k).collect();
+
+        // Assert
+        assert_eq!(vec![(5, 10), (6, 12), (7, 14), (8, 16)], inclusive);
+        assert_eq!(vec![5, 6, 7], exclusive);
+        assert_eq!(vec![1, 2], unbounded_start);
+        assert_eq!(vec![18, 19, 20], unbounded_end);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_in_different_versions() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+
+        // Act
+        for key in (2..=20).step_by(2) {
+            gojo.insert(key, key);
+        }
+        gojo.remove(&10);
+        let version_five: Vec<usize> = gojo.range(5, 3..=15)?.map(|(k, _)| *k).collect();
+        let latest: Vec<usize> = gojo
+            .range(gojo.latest_version(), 3..=15)?
+            .map(|(k, _)| *k)
+            .collect();
+        let excluded_start: Vec<usize> = gojo
+            .range(5, (Bound::Excluded(4), Bound::Unbounded))?
+            .map(|(k, _)| *k)
+            .collect();
+
+        // Assert
+        assert_eq!(vec![4, 6, 8, 10], version_five);
+        assert_eq!(vec![4, 6, 8, 12, 14], latest);
+        assert_eq!(vec![6, 8, 10], excluded_start);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_range() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+
+        // Act
+        for key in 1..=100 {
+            gojo.insert(key, key);
+        }
+
+        // Assert
+        assert_eq!(10, gojo.count_range(100, 11..=20)?);
+        assert_eq!(0, gojo.count_range(100, 200..)?);
+        assert_eq!(0, gojo.count_range(0, ..)?);
+        assert_eq!(5, gojo.count_range(50, 46..)?);
+        assert!(gojo.count_range(101, ..).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_range_matches_range_on_branches() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut gojo: Gojo<i32, i32> = Gojo::with_policy(10, DuplicatePolicy::Multi);
+        for _ in 0..300 {
+            let from = rng.gen_range(0..=gojo.latest_version());
+            let key = rng.gen_range(0..60);
+            if rng.gen_bool(0.3) {
+                gojo.remove_at(from, &key)?;
+            } else {
+                gojo.insert_at(from, key, key)?;
+            }
+        }
+
+        // Act
+        let mut mismatches = Vec::new();
+        for version in 0..=gojo.latest_version() {
+            let (lo, hi) = (rng.gen_range(-5..65), rng.gen_range(-5..65));
+            let bounds = [
+                (Bound::Included(lo), Bound::Excluded(hi)),
+                (Bound::Excluded(lo), Bound::Included(hi)),
+                (Bound::Unbounded, Bound::Included(hi)),
+                (Bound::Excluded(lo), Bound::Unbounded),
+            ];
+            for range in bounds {
+                let expected = gojo.range(version, range)?.count();
+                if gojo.count_range(version, range)? != expected {
+                    mismatches.push((version, range));
+                }
+            }
+        }
+
+        // Assert
+        assert_eq!(Vec::<(usize, (Bound<i32>, Bound<i32>))>::new(), mismatches);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_or_replace_keeps_old_values() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=10 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        let replaced = gojo.insert_or_replace(5, 50);
+        let inserted = gojo.insert_or_replace(11, 11);
+
+        // Assert
+        assert_eq!(Some(5), replaced);
+        assert_eq!(None, inserted);
+        assert_eq!(12, gojo.latest_version());
+        assert_eq!(Some(&5), gojo.get(&5, 10));
+        assert_eq!(Some(&50), gojo.get(&5, 11));
+        assert_eq!(Some(&50), gojo.get(&5, 12));
+        assert_eq!(Some(10), gojo.len(11));
+        assert_eq!(Some(11), gojo.len(12));
+    }
+
+    #[test]
+    fn test_update_every_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=10 {
+            gojo.insert(key, 0);
+        }
+
+        // Act
+        for _ in 0..20 {
+            for key in 1..=10 {
+                assert!(gojo.update(&key, |v| v + key));
+            }
+        }
+
+        // Assert
+        for version in 10..=gojo.latest_version() {
+            let round = (version - 10) / 10;
+            let updated_keys = (version - 10) % 10;
+            for (k, v) in gojo.iter(version)? {
+                let rounds = if *k <= updated_keys { round + 1 } else { round };
+                assert_eq!(k * rounds, *v, "for key {k} in version {version}");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_missing_key() {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        gojo.insert(1, 1);
+
+        // Act
+        let updated = gojo.update(&2, |v| v + 1);
+
+        // Assert
+        assert!(!updated);
+        assert_eq!(1, gojo.latest_version());
+    }
+
+    #[test]
+    fn test_update_in_branch() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=5 {
+            gojo.insert(key, key);
+        }
+        gojo.update(&3, |v| v * 100);
+        gojo.insert_at(2, 6, 6)?;
+
+        // Act
+        gojo.update(&2, |v| v * 10);
+
+        // Assert
+        let branch: Vec<(usize, usize)> = gojo.iter(8)?.map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(vec![(1, 1), (2, 20), (6, 6)], branch);
+        assert_eq!(Some(&2), gojo.get(&2, 6));
+        assert_eq!(Some(&300), gojo.get(&3, 6));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_and_values_without_default() -> Result<()> {
+        // Arrange
+        #[derive(Clone, PartialEq)]
+        struct Handle(String);
+        let mut gojo: Gojo<NonZeroU64, Handle> = Gojo::default();
+        let key = |k: u64| NonZeroU64::new(k).unwrap();
+
+        // Act
+        for k in 1..=10 {
+            gojo.insert(key(k), Handle(format!("h{k}")));
+        }
+        gojo.remove(&key(4));
+
+        // Assert
+        let keys: Vec<u64> = gojo.iter(11)?.map(|(k, _)| k.get()).collect();
+        assert_eq!(vec![1, 2, 3, 5, 6, 7, 8, 9, 10], keys);
+        assert!(gojo.get(&key(7), 11) == Some(&Handle("h7".to_string())));
+        assert!(gojo.get(&key(4), 10).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_queries_on_empty_versions() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=8 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        for key in 1..=8 {
+            gojo.remove(&key);
+        }
+
+        // Assert
+        for version in [0, 16] {
+            assert_eq!(0, gojo.iter(version)?.count());
+            assert_eq!(0, gojo.node_info_iter(version)?.count());
+            assert_eq!(None, gojo.get(&1, version));
+            assert_eq!(None, gojo.successor_by_key(&1, version));
+        }
+        assert_eq!(8, gojo.iter(8)?.count());
+
+        Ok(())
+    }
+
+    fn is_pruned<T>(result: Result<T>) -> bool {
+        result.is_err_and(|err| matches!(err.downcast_ref(), Some(GojoError::VersionPruned(_))))
+    }
+
+    #[test]
+    fn test_truncate_history_rejects_pruned_versions() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=20 {
+            gojo.insert(key, key);
+        }
+
+        // Act
+        gojo.truncate_history(10)?;
+
+        // Assert
+        for version in 0..10 {
+            assert!(is_pruned(gojo.iter(version)));
+            assert!(is_pruned(gojo.node_info_iter(version)));
+            assert!(is_pruned(gojo.insert_at(version, 42, 42)));
+            assert!(is_pruned(gojo.remove_at(version, &1)));
+            assert_eq!(None, gojo.get(&1, version));
+            assert_eq!(None, gojo.len(version));
+        }
+        assert!(gojo.truncate_history(21).is_err());
+        for version in 10..=20 {
+            assert_eq!(version, gojo.iter(version)?.count());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_history_frees_nodes() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=100 {
+            gojo.insert(key, key);
+        }
+        for key in 1..=90 {
+            gojo.remove(&key);
+        }
+        let nodes_before = gojo.arena.nodes.len();
+
+        // Act
+        gojo.truncate_history(gojo.latest_version())?;
+
+        // Assert
+        assert!(nodes_before > 100);
+        assert_eq!(10, gojo.arena.nodes.len());
+        let expected: Vec<usize> = (91..=100).collect();
+        let actual: Vec<usize> = gojo.iter(190)?.map(|(k, _)| *k).collect();
+        assert_eq!(expected, actual);
+        for node in gojo.arena.nodes.iter() {
+            assert!(node.mods.is_empty());
+            assert!(node.next_copy.is_null());
+            assert_eq!(190, node.version);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_history_keeps_node_infos() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        let mut rng = StdRng::seed_from_u64(0xB0CA);
+        for _ in 0..300 {
+            let key = rng.gen_range(0..60);
+            if gojo.contains_key(&key, gojo.latest_version()) {
+                gojo.remove(&key);
+            } else {
+                gojo.insert(key, key);
+            }
+        }
+        let infos = |gojo: &Gojo<usize, usize>, version: usize| -> Result<Vec<_>> {
+            Ok(gojo
+                .node_info_iter(version)?
+                .map(|info| (info.key, info.depth, info.color))
+                .collect())
+        };
+        let keep_from = 150;
+        let expected: Vec<_> = (keep_from..=gojo.latest_version())
+            .map(|version| infos(&gojo, version))
+            .collect::<Result<_>>()?;
+
+        // Act
+        gojo.truncate_history(keep_from)?;
+
+        // Assert
+        for (version, expected) in (keep_from..).zip(expected) {
+            assert_eq!(expected, infos(&gojo, version)?, "for version {version}");
+        }
+        for node in gojo.arena.nodes.iter() {
+            assert!(node.version >= keep_from);
+            assert!(node.mods.iter().all(|m| m.version >= keep_from));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_branches_match_naive_snapshots_after_truncate() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        let mut snapshots: Vec<BTreeSet<i32>> = vec![BTreeSet::new()];
+        let mut rng = StdRng::seed_from_u64(0xDEAD);
+        let mut first = 0;
+
+        // Act
+        for round in 1..=6 {
+            for _ in 0..150 {
+                let from = rng.gen_range(first..snapshots.len());
+                let key = rng.gen_range(0..60);
+                let mut snapshot = snapshots[from].clone();
+                if snapshot.remove(&key) {
+                    gojo.remove_at(from, &key)?;
+                } else {
+                    snapshot.insert(key);
+                    gojo.insert_at(from, key, key << 1)?;
+                }
+                snapshots.push(snapshot);
+            }
+            first = round * 100;
+            gojo.truncate_history(first)?;
+        }
+
+        // Assert
+        for (version, snapshot) in snapshots.iter().enumerate().skip(first) {
+            let expected: Vec<i32> = snapshot.iter().copied().collect();
+            let actual: Vec<i32> = gojo.iter(version)?.map(|(k, _)| *k).collect();
+            assert_eq!(expected, actual, "for version {version}");
+            assert_eq!(Some(snapshot.len()), gojo.len(version));
+            for (i, key) in expected.iter().enumerate() {
+                assert_eq!(Some(key), gojo.select(i, version)?.map(|(k, _)| k));
+                assert_eq!(Some(i), gojo.rank(key, version)?);
+            }
+            for key in 0..60 {
+                let expected = snapshot.contains(&key).then_some(key << 1);
+                assert_eq!(expected.as_ref(), gojo.get(&key, version));
+            }
+        }
+        assert!(is_pruned(gojo.iter(first - 1)));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_write_and_read_branches_after_truncate() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, String> = Gojo::default();
+        for key in 1..=30 {
+            gojo.insert(key, format!("v{key}"));
+        }
+        gojo.remove_at(10, &3)?;
+        gojo.update(&7, |v| format!("{v}!"));
+        gojo.truncate_history(5)?;
+        let mut buffer = Vec::new();
+
+        // Act
+        gojo.write_to(&mut buffer)?;
+        let mut read: Gojo<usize, String> = Gojo::read_from(buffer.as_slice())?;
+
+        // Assert
+        assert!(read.iter(4).is_err());
+        for version in 5..=gojo.latest_version() {
+            let expected: Vec<_> = gojo.iter(version)?.collect();
+            let actual: Vec<_> = read.iter(version)?.collect();
+            assert_eq!(expected, actual, "for version {version}");
+            assert_eq!(gojo.parent_version(version), read.parent_version(version));
+        }
+        gojo.insert_at(20, 99, "x".to_string())?;
+        read.insert_at(20, 99, "x".to_string())?;
+        let latest = gojo.latest_version();
+        assert_eq!(
+            gojo.iter(latest)?.collect::<Vec<_>>(),
+            read.iter(latest)?.collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_read_from_rejects_broken_trees() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=10 {
+            gojo.insert(key, key);
+        }
+        let mut out_of_arena = gojo.clone();
+        out_of_arena.arena.nodes[0].left = NodePtr(1000);
+        let mut unordered = gojo.clone();
+        unordered.arena.nodes[0].key = 99;
+        let mut looping = gojo.clone();
+        looping.arena.nodes[3].next_copy = NodePtr(3);
+        let mut future = gojo.clone();
+        future.arena.nodes[5].version = 1000;
+
+        // Act
+        let errors: Vec<GojoError> = [out_of_arena, unordered, looping, future]
+            .iter()
+            .map(|broken| {
+                let mut buffer = Vec::new();
+                broken.write_to(&mut buffer)?;
+                let err = Gojo::<usize, usize>::read_from(buffer.as_slice()).unwrap_err();
+                err.downcast::<GojoError>()
+            })
+            .collect::<Result<_>>()?;
+
+        // Assert
+        for err in errors {
+            assert!(matches!(err, GojoError::BrokenInvariant(_)), "{err}");
+        }
+        let mut buffer = Vec::new();
+        gojo.write_to(&mut buffer)?;
+        assert!(Gojo::<usize, usize>::read_from(buffer.as_slice()).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone_keeps_every_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=40 {
+            gojo.insert(key, key);
+        }
+        gojo.remove_at(15, &4)?;
+        gojo.insert_at(7, 100, 100)?;
+        gojo.update(&9, |v| v * 2);
+
+        // Act
+        let mut clone = gojo.clone();
+
+        // Assert
+        assert_eq!(gojo.latest_version(), clone.latest_version());
+        for version in 0..=gojo.latest_version() {
+            let expected: Vec<_> = gojo.iter(version)?.collect();
+            let actual: Vec<_> = clone.iter(version)?.collect();
+            assert_eq!(expected, actual, "for version {version}");
+            assert_eq!(gojo.len(version), clone.len(version));
+            assert_eq!(gojo.parent_version(version), clone.parent_version(version));
+        }
+        clone.insert_at(20, 0, 0)?;
+        assert_eq!(Some(&0), clone.get(&0, clone.latest_version()));
+        assert_eq!(None, gojo.get(&0, clone.latest_version()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_materializes_one_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<usize, usize> = Gojo::default();
+        for key in 1..=30 {
+            gojo.insert(key, key);
+        }
+        gojo.remove_at(20, &5)?;
+        let version = gojo.latest_version();
+        gojo.insert(50, 50);
+
+        // Act
+        let mut snapshot = gojo.snapshot(version)?;
+
+        // Assert
+        assert_eq!(0, snapshot.latest_version());
+        assert_eq!(gojo.len(version), snapshot.len(0));
+        assert_eq!(
+            gojo.iter(version)?.collect::<Vec<_>>(),
+            snapshot.iter(0)?.collect::<Vec<_>>()
+        );
+        assert_eq!(19, snapshot.arena.nodes.len());
+        snapshot.insert(5, 5);
+        snapshot.remove(&10);
+        let expected: Vec<usize> = (1..=20).filter(|key| *key != 10).collect();
+        let actual: Vec<usize> = snapshot.iter(2)?.map(|(k, _)| *k).collect();
+        assert_eq!(expected, actual);
+        assert!(gojo.snapshot(gojo.latest_version() + 1).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_sorted_iter_builds_a_balanced_version() -> Result<()> {
+        for len in 0..=70usize {
+            // Arrange
+            let pairs: Vec<(usize, usize)> = (0..len).map(|k| (k * 2, k)).collect();
+
+            // Act
+            let gojo: Gojo<usize, usize> =
+                Gojo::from_sorted_iter(pairs.clone(), DuplicatePolicy::Reject)?;
+
+            // Assert
+            assert_eq!(1, gojo.latest_version());
+            assert_eq!(Some(0), gojo.len(0));
+            assert_eq!(Some(len), gojo.len(1));
+            assert_eq!(len, gojo.arena.nodes.len());
+            assert!(
+                gojo.validate_all().is_ok(),
+                "{len}: {:?}",
+                gojo.validate_all()
+            );
+            let actual: Vec<(usize, usize)> = gojo.iter(1)?.map(|(k, v)| (*k, *v)).collect();
+            assert_eq!(pairs, actual);
+            let depth = gojo.node_info_iter(1)?.map(|info| info.depth).max();
+            assert_eq!(len.checked_ilog2().map(|d| d as usize), depth);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_sorted_iter_keeps_history_working() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> =
+            Gojo::from_sorted_iter((0..100).map(|k| (k, k)), DuplicatePolicy::Reject)?;
+
+        // Act
+        for k in (0..100).step_by(3) {
+            gojo.remove(&k);
+        }
+        gojo.insert_at(1, 1000, 1000)?;
+
+        // Assert
+        assert!(gojo.validate_all().is_ok(), "{:?}", gojo.validate_all());
+        assert_eq!(Some(100), gojo.len(1));
+        assert_eq!(Some(66), gojo.len(35));
+        assert_eq!(Some(101), gojo.len(36));
+        assert_eq!(Some(&0), gojo.get(&0, 1));
+        assert_eq!(None, gojo.get(&0, 35));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_sorted_iter_rejects_unsorted_keys() -> Result<()> {
+        // Arrange
+        let pairs = [(1, 1), (2, 2), (2, 3), (1, 4)];
+
+        // Act
+        let err = Gojo::<i32, i32>::from_sorted_iter(pairs, DuplicatePolicy::Reject).unwrap_err();
+
+        // Assert
+        assert_eq!(
+            "the key at position `3` is smaller than the one before it",
+            err.downcast::<GojoError>()?.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_iter_sorts_and_keeps_last_duplicate() -> Result<()> {
+        // Arrange
+        let pairs = [(5, "e"), (1, "a"), (3, "c"), (1, "y"), (4, "d"), (1, "z")];
+
+        // Act
+        let gojo: Gojo<i32, &str> = pairs.into_iter().collect();
+
+        // Assert
+        assert_eq!(1, gojo.latest_version());
+        assert_eq!(
+            vec![(&1, &"z"), (&3, &"c"), (&4, &"d"), (&5, &"e")],
+            gojo.iter(1)?.collect::<Vec<_>>()
+        );
+        assert_eq!(1, gojo.count(&1, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_iter_keeps_the_value_a_btree_map_keeps() {
+        // Arrange
+        let pairs = [(3, 1), (1, 1), (3, 2), (2, 2)];
+
+        // Act
+        let gojo: Gojo<i32, i32> = pairs.into_iter().collect();
+        let map: BTreeMap<i32, i32> = pairs.into_iter().collect();
+
+        // Assert
+        assert_eq!(Some(&2), gojo.get(&3, 1));
+        for (k, v) in map.iter() {
+            assert_eq!(Some(v), gojo.get(k, 1));
+        }
+        assert_eq!(Some(map.len()), gojo.len(1));
+    }
+
+    #[test]
+    fn test_from_sorted_iter_handles_duplicates_as_the_policy_says() -> Result<()> {
+        // Arrange
+        let pairs = [(1, 1), (2, 2), (2, 20), (2, 200), (3, 3)];
+
+        // Act
+        let replace: Gojo<i32, i32, 2, Sum> =
+            Gojo::from_sorted_iter(pairs, DuplicatePolicy::Replace)?;
+        let mut multi: Gojo<i32, i32, 2> = Gojo::from_sorted_iter(pairs, DuplicatePolicy::Multi)?;
+        multi.remove(&2);
+
+        // Assert
+        assert_eq!(Some(&200), replace.get(&2, 1));
+        assert_eq!(204, replace.aggregate(1, ..)?);
+        assert_eq!(DuplicatePolicy::Replace, replace.policy);
+        assert_eq!(3, multi.count(&2, 1));
+        assert_eq!(2, multi.count(&2, 2));
+        assert_eq!(Some(&2), multi.get(&2, 2));
+        assert!(
+            replace.validate_all().is_ok(),
+            "{:?}",
+            replace.validate_all()
+        );
+        assert!(multi.validate_all().is_ok(), "{:?}", multi.validate_all());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extend_makes_a_single_version() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32> = Gojo::from_sorted_iter([(1, 1)], DuplicatePolicy::Replace)?;
+
+        // Act
+        gojo.extend([(3, 3), (2, 2), (1, 10)]);
+        gojo.extend(Vec::new());
+
+        // Assert
+        assert_eq!(2, gojo.latest_version());
+        assert_eq!(
+            vec![(&1, &10), (&2, &2), (&3, &3)],
+            gojo.iter(2)?.collect::<Vec<_>>()
+        );
+        assert_eq!(vec![(&1, &1)], gojo.iter(1)?.collect::<Vec<_>>());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,346 @@
+use std::ops::Add;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Summary kept by every node of a `Gojo` for the keys and values of its
+/// subtree, so `Gojo::aggregate` answers a whole range in O(log n).
+/// `combine` must be associative and `empty` neutral for it.
+pub trait Monoid<K, V> {
+    /// Compared so that a node only records a summary that changed.
+    type Summary: Clone + PartialEq;
+
+    fn empty() -> Self::Summary;
+
+    /// Summary of a single key and its value, taken once for a key counted
+    /// many times under `DuplicatePolicy::Multi`.
+    fn lift(k: &K, v: &V) -> Self::Summary;
+
+    /// Summary of the keys of `a` followed by the ones of `b`.
+    fn combine(a: &Self::Summary, b: &Self::Summary) -> Self::Summary;
+}
+
+/// No summary at all, the default. Nodes keep nothing and updates record
+/// nothing for it.
+impl<K, V> Monoid<K, V> for () {
+    type Summary = ();
+
+    fn empty() {}
+
+    fn lift(_: &K, _: &V) {}
+
+    fn combine(_: &(), _: &()) {}
+}
+
+/// Sum of the values, `V::default()` for an empty range. The value of a key
+/// counted many times under `DuplicatePolicy::Multi` is still added once.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sum;
+
+impl<K, V: Clone + Default + PartialEq + Add<Output = V>> Monoid<K, V> for Sum {
+    type Summary = V;
+
+    fn empty() -> V {
+        V::default()
+    }
+
+    fn lift(_: &K, v: &V) -> V {
+        v.clone()
+    }
+
+    fn combine(a: &V, b: &V) -> V {
+        a.clone() + b.clone()
+    }
+}
+
+/// Greatest value, `None` for an empty range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Max;
+
+impl<K, V: Clone + Ord> Monoid<K, V> for Max {
+    type Summary = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn lift(_: &K, v: &V) -> Option<V> {
+        Some(v.clone())
+    }
+
+    fn combine(a: &Option<V>, b: &Option<V>) -> Option<V> {
+        a.clone().max(b.clone())
+    }
+}
+
+/// Smallest value, `None` for an empty range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Min;
+
+impl<K, V: Clone + Ord> Monoid<K, V> for Min {
+    type Summary = Option<V>;
+
+    fn empty() -> Option<V> {
+        None
+    }
+
+    fn lift(_: &K, v: &V) -> Option<V> {
+        Some(v.clone())
+    }
+
+    fn combine(a: &Option<V>, b: &Option<V>) -> Option<V> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.min(b).clone()),
+            _ => a.clone().or_else(|| b.clone()),
+        }
+    }
+}
+
+/// Amount of keys, a key counted many times under `DuplicatePolicy::Multi`
+/// is still one key.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Count;
+
+impl<K, V> Monoid<K, V> for Count {
+    type Summary = usize;
+
+    fn empty() -> usize {
+        0
+    }
+
+    fn lift(_: &K, _: &V) -> usize {
+        1
+    }
+
+    fn combine(a: &usize, b: &usize) -> usize {
+        a + b
+    }
+}
+
+#[cfg(test)]
+mod monoid_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{Count, Max, Min, Sum};
+    use crate::gojo::{DuplicatePolicy, Gojo, GojoError};
+    use anyhow::Result;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+
+    fn random_sums_with_mods<const MODS: usize>() -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(24);
+        let mut gojo: Gojo<i32, i64, MODS, Sum> = Gojo::with_mods(10, DuplicatePolicy::Replace);
+        let mut snapshots = vec![BTreeMap::new()];
+        for _ in 0..300 {
+            let from = rng.gen_range(snapshots.len().saturating_sub(10)..snapshots.len());
+            let mut snapshot: BTreeMap<i32, i64> = snapshots[from].clone();
+            let key = rng.gen_range(0..50);
+            match snapshot.contains_key(&key) && rng.gen_bool(0.4) {
+                true => {
+                    snapshot.remove(&key);
+                    gojo.remove_at(from, &key)?;
+                }
+                false => {
+                    let value = rng.gen_range(-100..100);
+                    snapshot.insert(key, value);
+                    gojo.insert_at(from, key, value)?;
+                }
+            }
+            snapshots.push(snapshot);
+        }
+
+        gojo.validate_all()?;
+        for (version, snapshot) in snapshots.iter().enumerate() {
+            for _ in 0..10 {
+                let lo = rng.gen_range(-5..55);
+                let hi = rng.gen_range(lo + 1..60);
+                let start = match rng.gen_range(0..3) {
+                    0 => Bound::Included(lo),
+                    1 => Bound::Excluded(lo),
+                    _ => Bound::Unbounded,
+                };
+                let end = match rng.gen_range(0..3) {
+                    0 => Bound::Included(hi),
+                    1 => Bound::Excluded(hi),
+                    _ => Bound::Unbounded,
+                };
+                let expected: i64 = snapshot.range((start, end)).map(|(_, v)| v).sum();
+                let actual = gojo.aggregate(version, (start, end))?;
+                assert_eq!(
+                    expected, actual,
+                    "for version {version} and {start:?}..{end:?}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_sums_match_naive_snapshots() -> Result<()> {
+        random_sums_with_mods::<6>()
+    }
+
+    #[test]
+    fn test_random_sums_match_naive_snapshots_with_copies() -> Result<()> {
+        random_sums_with_mods::<2>()
+    }
+
+    #[test]
+    fn test_replaced_values_change_the_sum_of_new_versions_only() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<&str, i32, 6, Sum> = Gojo::with_mods(10, DuplicatePolicy::Replace);
+        for (k, v) in [("a", 1), ("b", 2), ("c", 3)] {
+            gojo.insert(k, v);
+        }
+
+        // Act
+        gojo.insert("b", 20);
+        gojo.update(&"c", |v| v * 10);
+        gojo.batch(|tx| {
+            tx.insert("a", 100);
+            tx.insert("d", 4);
+            Ok(())
+        })?;
+
+        // Assert
+        assert_eq!(6, gojo.aggregate(3, ..)?);
+        assert_eq!(24, gojo.aggregate(4, ..)?);
+        assert_eq!(51, gojo.aggregate(5, ..)?);
+        assert_eq!(154, gojo.aggregate(6, ..)?);
+        assert_eq!(120, gojo.aggregate(6, "a".."c")?);
+        gojo.validate_all()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_min_and_count_of_ranges() -> Result<()> {
+        // Arrange
+        let pairs = [(1, 7), (2, 3), (3, 9), (4, 1), (5, 5)];
+        let mut max: Gojo<i32, i32, 6, Max> = Gojo::with_mods(10, Default::default());
+        let mut min: Gojo<i32, i32, 6, Min> = Gojo::with_mods(10, Default::default());
+        let mut count: Gojo<i32, i32, 6, Count> = Gojo::with_mods(10, Default::default());
+
+        // Act
+        for (k, v) in pairs {
+            max.insert(k, v);
+            min.insert(k, v);
+            count.insert(k, v);
+        }
+        max.remove(&3);
+        min.remove(&4);
+        count.remove(&1);
+
+        // Assert
+        assert_eq!(Some(9), max.aggregate(5, ..)?);
+        assert_eq!(Some(7), max.aggregate(6, ..)?);
+        assert_eq!(Some(5), max.aggregate(6, 2..)?);
+        assert_eq!(None, max.aggregate(6, 3..4)?);
+        assert_eq!(Some(1), min.aggregate(5, 2..=4)?);
+        assert_eq!(Some(3), min.aggregate(6, 2..=4)?);
+        assert_eq!(None, min.aggregate(0, ..)?);
+        assert_eq!(5, count.aggregate(5, ..)?);
+        assert_eq!(2, count.aggregate(6, ..4)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_keeps_subtree_summaries() -> Result<()> {
+        // Arrange
+        let mut gojo: Gojo<i32, i32, 2, Sum> = Gojo::with_mods(10, DuplicatePolicy::Reject);
+        for k in 0..20 {
+            gojo.insert(k, k);
+        }
+        gojo.insert(100, 100);
+
+        // Act
+        let mut snapshot = gojo.snapshot(20)?;
+        for k in 20..40 {
+            snapshot.insert(k, k);
+        }
+        snapshot.remove(&0);
+        snapshot.remove(&19);
+
+        // Assert
+        assert_eq!(190, snapshot.aggregate(0, ..)?);
+        assert_eq!(45, snapshot.aggregate(0, ..10)?);
+        assert_eq!(780, snapshot.aggregate(20, ..)?);
+        assert_eq!(761, snapshot.aggregate(22, ..)?);
+        snapshot.validate_all()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keys_counted_many_times_are_summed_once() -> Result<()> {
+        // Arrange
+        let mut sum: Gojo<i32, i32, 6, Sum> = Gojo::with_mods(10, DuplicatePolicy::Multi);
+        let mut count: Gojo<i32, i32, 6, Count> = Gojo::with_mods(10, DuplicatePolicy::Multi);
+
+        // Act
+        for (k, v) in [(1, 10), (2, 20), (2, 20), (2, 20), (3, 30)] {
+            sum.insert(k, v);
+            count.insert(k, v);
+        }
+        sum.remove(&2);
+
+        // Assert
+        assert_eq!(3, sum.count(&2, 5));
+        assert_eq!(60, sum.aggregate(5, ..)?);
+        assert_eq!(60, sum.aggregate(6, ..)?);
+        assert_eq!(3, count.aggregate(5, ..)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unchanged_summaries_spend_no_mods() -> Result<()> {
+        // Arrange
+        let pairs = || (0..64).map(|k| (k, k));
+        let mut plain: Gojo<i32, i32, 2> =
+            Gojo::from_sorted_iter(pairs(), DuplicatePolicy::Replace)?;
+        let mut count: Gojo<i32, i32, 2, Count> =
+            Gojo::from_sorted_iter(pairs(), DuplicatePolicy::Replace)?;
+        let before = (plain.arena_stats(), count.arena_stats());
+
+        // Act
+        for k in (0..64).cycle().take(300) {
+            plain.insert(k, -k);
+            count.insert(k, -k);
+        }
+
+        // Assert
+        // Replacing a value changes no amount of keys, so the summaries cost
+        // no more nodes than the values themselves
+        let (plain_after, count_after) = (plain.arena_stats(), count.arena_stats());
+        assert_eq!(
+            plain_after.copies - before.0.copies,
+            count_after.copies - before.1.copies
+        );
+        assert_eq!(
+            plain_after.nodes - before.0.nodes,
+            count_after.nodes - before.1.nodes
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_of_unknown_version() -> Result<()> {
+        // Arrange
+        let gojo: Gojo<i32, i32, 6, Sum> = Gojo::with_mods(10, Default::default());
+
+        // Act
+        let err = gojo.aggregate(1, ..).unwrap_err().downcast::<GojoError>()?;
+
+        // Assert
+        assert_eq!("the version `1` is not available", err.to_string());
+
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@ pub enum Statement {
     Print(usize),
     Remove(i32),
     Successor { value: i32, version: usize },
+    Predecessor { value: i32, version: usize },
 }
 
 pub trait Parser {
@@ -50,11 +51,11 @@ impl Parser for ParserVagaba {
         if tokens.len() == 3 {
             let value: i32 = tokens[1].parse()?;
             let version: usize = tokens[2].parse()?;
-            if stm.to_lowercase() != "suc" {
-                anyhow::bail!("Esperado sucessor");
-            }
-
-            return Ok(Statement::Successor { value, version });
+            return match stm.to_lowercase().as_str() {
+                "suc" => Ok(Statement::Successor { value, version }),
+                "ant" => Ok(Statement::Predecessor { value, version }),
+                _ => anyhow::bail!("Esperado sucessor ou antecessor"),
+            };
         }
 
         match stm.to_lowercase().as_str() {
@@ -149,6 +150,25 @@ mod parser_vagaba_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_predecessor_statement() -> Result<()> {
+        // Arrange
+        let s = "ANT 14 1";
+        let p = ParserVagaba::new();
+        let expected_stm = Statement::Predecessor {
+            value: 14,
+            version: 1,
+        };
+
+        // Act
+        let actual_stm = p.parse_line(s)?;
+
+        //Assert
+        assert_eq!(expected_stm, actual_stm);
+
+        Ok(())
+    }
+
     #[test]
     fn test_parse_lines() -> Result<()> {
         // Arrange
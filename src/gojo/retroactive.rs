@@ -0,0 +1,415 @@
+//! Partially retroactive sets, see `RetroactiveSet`.
+//!
+//! Times are not `Gojo` versions. A version of a `Gojo` is immutable once it
+//! is made, which is what persistence is for, while a retroactive change
+//! rewrites every time after the one it is made at. Keeping a version per
+//! time would mean changing, or making again, every version after `t` for a
+//! change at `t`, O(times) work for each late insert or remove. So only the
+//! present is a `GojoSet`, whose versions record how the present changed,
+//! and the past is kept as the lifespans of the keys in a segment tree over
+//! time: a change at `t` moves the lifespans of its own key only, each in
+//! O(log T) nodes, and a query at `t` reads the O(log T) nodes on its path.
+//!
+//! `Gojo` therefore only serves the present, its versions are not times and
+//! no query on the past goes through them. The history of each key is kept
+//! twice, outside of it: once as its changes by time, and once as its
+//! lifespans in the segment tree, where every lifespan clones the key in up
+//! to two nodes per level, about 128 for `u64` times, and may make as many
+//! nodes. On top of that the present keeps every version it went through,
+//! about one fat node copy per change of the present. Memory is thus
+//! O(C log T) keys for C changes, far more than the O(C) a history kept in
+//! `Gojo` versions would need, which is the price of O(log T) late changes.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use super::set::{GojoSet, GojoSetIter};
+
+/// A set whose history can be corrected: keys are inserted and removed at any
+/// time, also before changes already made, and every query sees the history
+/// as corrected so far.
+///
+/// It follows the partially retroactive search trees of Demaine, Iacono and
+/// Langerman. Changes to different keys commute, so a change of the past only
+/// touches the present through its own key, which the present `GojoSet` gets
+/// in a new version. Queries on a past time use the lifespans of the keys,
+/// split over a segment tree on time, where every time sees each key alive
+/// then in exactly one node of its path.
+#[derive(Debug, Clone)]
+pub struct RetroactiveSet<K: Ord + Clone> {
+    present: GojoSet<K>,
+    // Changes of every key by time, `true` for an insert
+    changes: BTreeMap<K, BTreeMap<u64, bool>>,
+    lifespans: Lifespans<K>,
+}
+
+impl<K: Ord + Clone> Default for RetroactiveSet<K> {
+    fn default() -> Self {
+        Self {
+            present: GojoSet::default(),
+            changes: BTreeMap::new(),
+            lifespans: Lifespans::default(),
+        }
+    }
+}
+
+impl<K: Ord + Clone> RetroactiveSet<K> {
+    pub fn new() -> RetroactiveSet<K> {
+        Self::default()
+    }
+
+    /// Records that `k` was inserted at time `t`, replacing the change of `k`
+    /// made at `t` if there was one. Returns whether the present changed.
+    pub fn insert_at_time(&mut self, t: u64, k: K) -> bool {
+        self.record(k, |changes| {
+            changes.insert(t, true);
+        })
+    }
+
+    /// Records that `k` was removed at time `t`, replacing the change of `k`
+    /// made at `t` if there was one. Returns whether the present changed.
+    pub fn remove_at_time(&mut self, t: u64, k: &K) -> bool {
+        self.record(k.clone(), |changes| {
+            changes.insert(t, false);
+        })
+    }
+
+    /// Forgets the change of `k` made at time `t`, as if it never happened.
+    /// Returns whether the present changed.
+    pub fn cancel_at_time(&mut self, t: u64, k: &K) -> bool {
+        if !self
+            .changes
+            .get(k)
+            .is_some_and(|changes| changes.contains_key(&t))
+        {
+            return false;
+        }
+        self.record(k.clone(), |changes| {
+            changes.remove(&t);
+        })
+    }
+
+    // Applies `change` to the history of `k` and brings its lifespans and the
+    // present in line with it
+    fn record(&mut self, k: K, change: impl FnOnce(&mut BTreeMap<u64, bool>)) -> bool {
+        let changes = self.changes.entry(k.clone()).or_default();
+        let before = lifespans_of(changes);
+        change(changes);
+        let after = lifespans_of(changes);
+        let alive = changes
+            .last_key_value()
+            .is_some_and(|(_, inserted)| *inserted);
+        if changes.is_empty() {
+            self.changes.remove(&k);
+        }
+
+        for span in before.iter().filter(|span| !after.contains(span)) {
+            self.lifespans.remove(*span, &k);
+        }
+        for span in after.iter().filter(|span| !before.contains(span)) {
+            self.lifespans.insert(*span, &k);
+        }
+        match alive {
+            true => self.present.insert(k),
+            false => self.present.remove(&k),
+        }
+    }
+
+    /// The present set, it has a version for every change of the history that
+    /// changed the present.
+    pub fn present(&self) -> &GojoSet<K> {
+        &self.present
+    }
+
+    pub fn len(&self) -> usize {
+        self.present
+            .len(self.present.latest_version())
+            .expect("the latest version is always there")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, k: &K) -> bool {
+        self.present.contains(k, self.present.latest_version())
+    }
+
+    /// Smallest key greater than `k` in the present.
+    pub fn successor(&self, k: &K) -> Option<&K> {
+        self.present.successor(k, self.present.latest_version())
+    }
+
+    /// Greatest key smaller than `k` in the present.
+    pub fn predecessor(&self, k: &K) -> Option<&K> {
+        self.present.predecessor(k, self.present.latest_version())
+    }
+
+    /// Keys of the present, in order.
+    pub fn iter(&self) -> GojoSetIter<'_, K> {
+        self.present
+            .iter(self.present.latest_version())
+            .expect("the latest version is always there")
+    }
+
+    /// Whether `k` was in the set at time `t`, after the changes made at `t`.
+    pub fn contains_at_time(&self, k: &K, t: u64) -> bool {
+        self.changes
+            .get(k)
+            .and_then(|changes| changes.range(..=t).next_back())
+            .is_some_and(|(_, inserted)| *inserted)
+    }
+
+    /// Keys in the set at time `t`.
+    pub fn len_at_time(&self, t: u64) -> usize {
+        self.lifespans.path(t).map(|keys| keys.len()).sum()
+    }
+
+    /// Smallest key greater than `k` at time `t`.
+    pub fn successor_at_time(&self, k: &K, t: u64) -> Option<&K> {
+        self.lifespans
+            .path(t)
+            .filter_map(|keys| keys.range((Bound::Excluded(k), Bound::Unbounded)).next())
+            .min()
+    }
+
+    /// Greatest key smaller than `k` at time `t`.
+    pub fn predecessor_at_time(&self, k: &K, t: u64) -> Option<&K> {
+        self.lifespans
+            .path(t)
+            .filter_map(|keys| keys.range(..k).next_back())
+            .max()
+    }
+}
+
+// Times, both inclusive, when a key with `changes` is in the set
+fn lifespans_of(changes: &BTreeMap<u64, bool>) -> Vec<(u64, u64)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (t, inserted) in changes {
+        match (start, inserted) {
+            (None, true) => start = Some(*t),
+            (Some(from), false) => {
+                spans.push((from, t - 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        spans.push((from, u64::MAX));
+    }
+    spans
+}
+
+// Segment tree over every `u64` time, nodes are made when a lifespan first
+// reaches them
+#[derive(Debug, Clone)]
+struct Lifespans<K: Ord + Clone> {
+    nodes: Vec<TimeNode<K>>,
+}
+
+#[derive(Debug, Clone)]
+struct TimeNode<K: Ord + Clone> {
+    // Keys alive over the whole range of the node, but not over its parent's
+    keys: BTreeSet<K>,
+    // Index of the children, 0 when there is none as the root is no child
+    children: [usize; 2],
+}
+
+impl<K: Ord + Clone> Default for Lifespans<K> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![TimeNode::new()],
+        }
+    }
+}
+
+impl<K: Ord + Clone> TimeNode<K> {
+    fn new() -> TimeNode<K> {
+        TimeNode {
+            keys: BTreeSet::new(),
+            children: [0, 0],
+        }
+    }
+}
+
+impl<K: Ord + Clone> Lifespans<K> {
+    fn insert(&mut self, span: (u64, u64), k: &K) {
+        self.update(0, (0, u64::MAX), span, k, true);
+    }
+
+    fn remove(&mut self, span: (u64, u64), k: &K) {
+        self.update(0, (0, u64::MAX), span, k, false);
+    }
+
+    // `range` of `node` must overlap `span`
+    fn update(&mut self, node: usize, range: (u64, u64), span: (u64, u64), k: &K, add: bool) {
+        let ((lo, hi), (from, to)) = (range, span);
+        if from <= lo && hi <= to {
+            let keys = &mut self.nodes[node].keys;
+            match add {
+                true => keys.insert(k.clone()),
+                false => keys.remove(k),
+            };
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        for (side, range) in [(0, (lo, mid)), (1, (mid + 1, hi))] {
+            if to < range.0 || range.1 < from {
+                continue;
+            }
+            let mut child = self.nodes[node].children[side];
+            if child == 0 {
+                if !add {
+                    continue;
+                }
+                child = self.nodes.len();
+                self.nodes.push(TimeNode::new());
+                self.nodes[node].children[side] = child;
+            }
+            self.update(child, range, span, k, add);
+        }
+    }
+
+    // Keys of every node whose range holds `t`, from the root down
+    fn path(&self, t: u64) -> impl Iterator<Item = &BTreeSet<K>> + '_ {
+        let mut next = Some((0, 0, u64::MAX));
+        std::iter::from_fn(move || {
+            let (node, lo, hi) = next?;
+            let mid = lo + (hi - lo) / 2;
+            let (side, lo, hi) = match t <= mid {
+                true => (0, lo, mid),
+                false => (1, mid + 1, hi),
+            };
+            let child = self.nodes[node].children[side];
+            next = (child != 0).then_some((child, lo, hi));
+            Some(&self.nodes[node].keys)
+        })
+    }
+}
+
+#[cfg(test)]
+mod retroactive_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::RetroactiveSet;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    // Replays every change up to time `t` on an empty set
+    fn replay(changes: &BTreeMap<(u64, i32), bool>, t: u64) -> BTreeSet<i32> {
+        let mut set = BTreeSet::new();
+        for ((_, k), inserted) in changes.range(..=(t, i32::MAX)) {
+            match inserted {
+                true => set.insert(*k),
+                false => set.remove(k),
+            };
+        }
+        set
+    }
+
+    #[test]
+    fn test_late_changes_correct_the_present() {
+        // Arrange
+        let mut retro = RetroactiveSet::new();
+        retro.insert_at_time(10, "a");
+        retro.insert_at_time(20, "b");
+        retro.remove_at_time(30, &"a");
+
+        // Act
+        let late_insert = retro.insert_at_time(40, "a");
+        let late_remove = retro.remove_at_time(5, &"b");
+        let earlier_insert = retro.insert_at_time(1, "c");
+
+        // Assert
+        assert!(late_insert);
+        assert!(!late_remove);
+        assert!(earlier_insert);
+        assert_eq!(vec![&"a", &"b", &"c"], retro.iter().collect::<Vec<_>>());
+        assert!(!retro.contains_at_time(&"a", 35));
+        assert!(retro.contains_at_time(&"a", 40));
+        assert!(!retro.contains_at_time(&"b", 19));
+        assert_eq!(Some(&"b"), retro.successor_at_time(&"a", 25));
+        assert_eq!(None, retro.predecessor_at_time(&"b", 35));
+        assert_eq!(3, retro.len_at_time(25));
+        assert_eq!(3, retro.len());
+    }
+
+    #[test]
+    fn test_cancel_forgets_a_change() {
+        // Arrange
+        let mut retro = RetroactiveSet::new();
+        retro.insert_at_time(10, 1);
+        retro.remove_at_time(20, &1);
+        let versions = retro.present().latest_version();
+
+        // Act
+        let cancelled = retro.cancel_at_time(20, &1);
+        let missing = retro.cancel_at_time(30, &1);
+
+        // Assert
+        assert!(cancelled);
+        assert!(!missing);
+        assert!(retro.contains(&1));
+        assert!(retro.contains_at_time(&1, 25));
+        assert_eq!(versions + 1, retro.present().latest_version());
+        retro.cancel_at_time(10, &1);
+        assert!(retro.is_empty());
+        assert_eq!(0, retro.len_at_time(15));
+    }
+
+    #[test]
+    fn test_random_history_matches_naive_replay() {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(23);
+        let mut retro = RetroactiveSet::new();
+        let mut changes: BTreeMap<(u64, i32), bool> = BTreeMap::new();
+
+        for step in 0..400 {
+            let t = rng.gen_range(0..60);
+            let k = rng.gen_range(0..25);
+
+            // Act
+            let was = replay(&changes, u64::MAX);
+            let changed = match rng.gen_range(0..5) {
+                0 => {
+                    changes.remove(&(t, k));
+                    retro.cancel_at_time(t, &k)
+                }
+                1 | 2 => {
+                    changes.insert((t, k), false);
+                    retro.remove_at_time(t, &k)
+                }
+                _ => {
+                    changes.insert((t, k), true);
+                    retro.insert_at_time(t, k)
+                }
+            };
+
+            // Assert
+            let present = replay(&changes, u64::MAX);
+            assert_eq!(was != present, changed, "at step {step}");
+            assert_eq!(
+                present.iter().collect::<Vec<_>>(),
+                retro.iter().collect::<Vec<_>>()
+            );
+            if step % 40 != 0 {
+                continue;
+            }
+            for t in 0..=60 {
+                let expected = replay(&changes, t);
+                assert_eq!(expected.len(), retro.len_at_time(t), "at time {t}");
+                for k in -1..=25 {
+                    let succ = expected.range(k + 1..).next();
+                    let pred = expected.range(..k).next_back();
+                    assert_eq!(expected.contains(&k), retro.contains_at_time(&k, t));
+                    assert_eq!(succ, retro.successor_at_time(&k, t), "{k} at {t}");
+                    assert_eq!(pred, retro.predecessor_at_time(&k, t), "{k} at {t}");
+                }
+            }
+        }
+        assert!(retro.present().latest_version() > 0);
+    }
+}
@@ -0,0 +1,302 @@
+use anyhow::Result;
+use itertools::{EitherOrBoth, Itertools};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::iter::FusedIterator;
+
+use super::{DiffEvent, DuplicatePolicy, Gojo, GojoIter, NodeInfoIter};
+
+/// A persistent ordered set: a `Gojo` whose nodes hold only their key.
+///
+/// Every change makes a new version and any version can be queried, or
+/// compared with another one.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GojoSet<K: Ord + Clone, const MODS: usize = 6> {
+    gojo: Gojo<K, (), MODS>,
+}
+
+impl<K: Ord + Clone> Default for GojoSet<K> {
+    fn default() -> Self {
+        Self::new(super::DEFAULT_MAX_OPS)
+    }
+}
+
+impl<K: Ord + Clone> GojoSet<K> {
+    /// Creates an empty `GojoSet`.
+    pub fn new(predected_amount_of_ops: usize) -> GojoSet<K> {
+        Self::with_mods(predected_amount_of_ops)
+    }
+}
+
+impl<K: Ord + Clone, const MODS: usize> GojoSet<K, MODS> {
+    /// Creates an empty `GojoSet` whose fat nodes hold up to `MODS` changes,
+    /// see `Gojo::with_mods`.
+    pub fn with_mods(predected_amount_of_ops: usize) -> GojoSet<K, MODS> {
+        GojoSet {
+            gojo: Gojo::with_mods(predected_amount_of_ops, DuplicatePolicy::Reject),
+        }
+    }
+
+    pub fn latest_version(&self) -> usize {
+        self.gojo.latest_version()
+    }
+
+    pub fn len(&self, version: usize) -> Option<usize> {
+        self.gojo.len(version)
+    }
+
+    pub fn is_empty(&self, version: usize) -> bool {
+        self.gojo.is_empty(version)
+    }
+
+    /// Inserts `k` in a new version derived from the latest one. Returns
+    /// whether it was not there yet, no version is created otherwise.
+    pub fn insert(&mut self, k: K) -> bool {
+        self.gojo.insert(k, ()).created_version()
+    }
+
+    /// Inserts `k` in a new version derived from `version`.
+    pub fn insert_at(&mut self, version: usize, k: K) -> Result<bool> {
+        Ok(self.gojo.insert_at(version, k, ())?.created_version())
+    }
+
+    /// Same as `insert`, but creates a version even if `k` is already there.
+    pub fn college_insert(&mut self, k: K) -> bool {
+        let from = self.gojo.latest_version();
+        if self.gojo.contains_key(&k, from) {
+            self.gojo.unchanged_version(from);
+            return false;
+        }
+
+        self.gojo.insert(k, ());
+        true
+    }
+
+    /// Removes `k` in a new version derived from the latest one. Returns
+    /// whether it was there, no version is created otherwise.
+    pub fn remove(&mut self, k: &K) -> bool {
+        self.gojo.remove(k).is_some()
+    }
+
+    /// Removes `k` in a new version derived from `version`.
+    pub fn remove_at(&mut self, version: usize, k: &K) -> Result<bool> {
+        Ok(self.gojo.remove_at(version, k)?.is_some())
+    }
+
+    /// Same as `remove`, but creates a version even if `k` is not there.
+    pub fn college_remove(&mut self, k: &K) -> bool {
+        self.gojo.college_remove(k).is_some()
+    }
+
+    pub fn contains(&self, k: &K, version: usize) -> bool {
+        self.gojo.contains_key(k, version)
+    }
+
+    /// Smallest key greater than `k` in `version`.
+    pub fn successor(&self, k: &K, version: usize) -> Option<&K> {
+        self.gojo.strict_successor(k, version).map(|(k, _)| k)
+    }
+
+    /// Greatest key smaller than `k` in `version`.
+    pub fn predecessor(&self, k: &K, version: usize) -> Option<&K> {
+        self.gojo.strict_predecessor(k, version).map(|(k, _)| k)
+    }
+
+    pub fn iter(&self, version: usize) -> Result<GojoSetIter<'_, K, MODS>> {
+        Ok(GojoSetIter {
+            inner: self.gojo.iter(version)?,
+        })
+    }
+
+    /// Nodes of `version` in order, with their depth and color.
+    pub fn node_info_iter(&self, version: usize) -> Result<NodeInfoIter<'_, K, (), MODS>> {
+        self.gojo.node_info_iter(version)
+    }
+
+    /// Keys in version `a` or in version `b`, in order.
+    pub fn union(&self, a: usize, b: usize) -> Result<impl Iterator<Item = &K> + '_> {
+        let merged = self.iter(a)?.merge_join_by(self.iter(b)?, |x, y| x.cmp(y));
+        Ok(merged.map(|keys| match keys {
+            EitherOrBoth::Both(k, _) | EitherOrBoth::Left(k) | EitherOrBoth::Right(k) => k,
+        }))
+    }
+
+    /// Keys in both version `a` and version `b`, in order.
+    pub fn intersection(&self, a: usize, b: usize) -> Result<impl Iterator<Item = &K> + '_> {
+        let merged = self.iter(a)?.merge_join_by(self.iter(b)?, |x, y| x.cmp(y));
+        Ok(merged.filter_map(|keys| match keys {
+            EitherOrBoth::Both(k, _) => Some(k),
+            EitherOrBoth::Left(_) | EitherOrBoth::Right(_) => None,
+        }))
+    }
+
+    /// Keys in version `a` but not in version `b`, in order. Subtrees both
+    /// versions share are skipped, so versions close in history are cheap to
+    /// compare.
+    pub fn difference(&self, a: usize, b: usize) -> Result<impl Iterator<Item = &K> + '_> {
+        Ok(self.gojo.diff(a, b)?.filter_map(|event| match event {
+            DiffEvent::Removed(k, _) => Some(k),
+            DiffEvent::Added(_, _) | DiffEvent::Changed { .. } => None,
+        }))
+    }
+}
+
+impl<K: Ord + Clone + Debug, const MODS: usize> GojoSet<K, MODS> {
+    /// Graphviz DOT of the set seen from `version`, see `Gojo::to_dot`.
+    pub fn to_dot(&self, version: usize) -> Result<String> {
+        self.gojo.dot(version, |k, _| format!("{k:?}"))
+    }
+
+    /// Graphviz DOT of every fat node of the set, see `Gojo::to_dot_fat`.
+    pub fn to_dot_fat(&self) -> String {
+        self.gojo.dot_fat(|k, _| format!("{k:?}"))
+    }
+}
+
+pub struct GojoSetIter<'a, K: Ord + Clone + 'a, const MODS: usize = 6> {
+    inner: GojoIter<'a, K, (), MODS>,
+}
+
+impl<'a, K: Ord + Clone + 'a, const MODS: usize> Clone for GojoSetIter<'a, K, MODS> {
+    fn clone(&self) -> GojoSetIter<'a, K, MODS> {
+        GojoSetIter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, const MODS: usize> Iterator for GojoSetIter<'a, K, MODS> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, const MODS: usize> DoubleEndedIterator for GojoSetIter<'a, K, MODS> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back().map(|(k, _)| k)
+    }
+}
+
+impl<'a, K: Ord + Clone + 'a, const MODS: usize> ExactSizeIterator for GojoSetIter<'a, K, MODS> {}
+
+impl<'a, K: Ord + Clone + 'a, const MODS: usize> FusedIterator for GojoSetIter<'a, K, MODS> {}
+
+#[cfg(test)]
+mod set_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::GojoSet;
+    use anyhow::Result;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_insert_and_remove_make_versions_only_on_change() -> Result<()> {
+        // Arrange
+        let mut set: GojoSet<i32> = GojoSet::default();
+
+        // Act
+        let inserted = [set.insert(3), set.insert(1), set.insert(3)];
+        let removed = [set.remove(&5), set.remove(&3)];
+
+        // Assert
+        assert_eq!([true, true, false], inserted);
+        assert_eq!([false, true], removed);
+        assert_eq!(3, set.latest_version());
+        assert_eq!(vec![&1, &3], set.iter(2)?.collect::<Vec<_>>());
+        assert_eq!(vec![&1], set.iter(3)?.collect::<Vec<_>>());
+        assert!(set.contains(&3, 2));
+        assert!(!set.contains(&3, 3));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_college_updates_always_make_versions() {
+        // Arrange
+        let mut set: GojoSet<i32> = GojoSet::default();
+        set.insert(1);
+
+        // Act
+        let inserted = set.college_insert(1);
+        let removed = set.college_remove(&2);
+
+        // Assert
+        assert!(!inserted);
+        assert!(!removed);
+        assert_eq!(3, set.latest_version());
+        assert_eq!(Some(1), set.len(3));
+    }
+
+    #[test]
+    fn test_successor_and_predecessor() {
+        // Arrange
+        let mut set: GojoSet<i32> = GojoSet::default();
+        for k in [10, 20, 30] {
+            set.insert(k);
+        }
+
+        // Act
+        let successors = [
+            set.successor(&10, 3),
+            set.successor(&15, 3),
+            set.successor(&30, 3),
+        ];
+        let predecessors = [set.predecessor(&10, 3), set.predecessor(&25, 3)];
+
+        // Assert
+        assert_eq!([Some(&20), Some(&20), None], successors);
+        assert_eq!([None, Some(&20)], predecessors);
+        assert_eq!(None, set.successor(&10, 1));
+    }
+
+    #[test]
+    fn test_set_algebra_between_versions_matches_btree_set() -> Result<()> {
+        // Arrange
+        let mut rng = StdRng::seed_from_u64(18);
+        let mut set: GojoSet<i32> = GojoSet::default();
+        let mut snapshots = vec![BTreeSet::new()];
+        for _ in 0..300 {
+            let from = rng.gen_range(snapshots.len().saturating_sub(30)..snapshots.len());
+            let key = rng.gen_range(0..50);
+            let mut snapshot: BTreeSet<i32> = snapshots[from].clone();
+            if snapshot.remove(&key) {
+                set.remove_at(from, &key)?;
+            } else {
+                snapshot.insert(key);
+                set.insert_at(from, key)?;
+            }
+            snapshots.push(snapshot);
+        }
+
+        for _ in 0..100 {
+            let a = rng.gen_range(0..snapshots.len());
+            let b = rng.gen_range(0..snapshots.len());
+
+            // Act
+            let union: Vec<&i32> = set.union(a, b)?.collect();
+            let intersection: Vec<&i32> = set.intersection(a, b)?.collect();
+            let difference: Vec<&i32> = set.difference(a, b)?.collect();
+
+            // Assert
+            let (x, y) = (&snapshots[a], &snapshots[b]);
+            assert_eq!(x.union(y).collect::<Vec<_>>(), union, "{a} | {b}");
+            assert_eq!(
+                x.intersection(y).collect::<Vec<_>>(),
+                intersection,
+                "{a} & {b}"
+            );
+            assert_eq!(x.difference(y).collect::<Vec<_>>(), difference, "{a} - {b}");
+        }
+
+        Ok(())
+    }
+}
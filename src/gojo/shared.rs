@@ -0,0 +1,461 @@
+use anyhow::Result;
+#[cfg(loom)]
+use loom::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    sync::Arc,
+    thread,
+};
+#[cfg(not(loom))]
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    sync::Arc,
+    thread,
+};
+
+use super::{batch::GojoTx, Gojo, GojoError, InsertOutcome, Monoid};
+
+// Bits of the state of a shared tree: the active copy, then how many readers
+// are on each copy
+const ACTIVE: u64 = 1;
+const READER: [u64; 2] = [1 << 1, 1 << 32];
+const READERS: [u64; 2] = [((1 << 31) - 1) << 1, u32::MAX as u64 * READER[1]];
+
+// Two copies of the tree, readers only ever use the active one and the writer
+// only ever changes the other one. An update is made on the inactive copy,
+// which is then made the active one and its version published. Once the
+// readers left the copy that was active before, the writer makes the same
+// update on it, so both copies are the same again between updates.
+//
+// A reader never waits: it registers on a copy and learns which one is active
+// in a single change of `state`, trying again in the rare case the writer
+// switched copies just before. Every access to `state` is a read-modify-write
+// but the wait of the writer, so they all follow a single order.
+struct Shared<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> {
+    trees: [UnsafeCell<Gojo<K, V, MODS, M>>; 2],
+    state: AtomicU64,
+    latest: AtomicUsize,
+}
+
+// Readers only get shared references to a copy, and the single writer only
+// changes a copy no reader is on
+unsafe impl<K, V, const MODS: usize, M> Sync for Shared<K, V, MODS, M>
+where
+    K: Ord + Clone + Send + Sync,
+    V: Clone + Send + Sync,
+    M: Monoid<K, V>,
+    Gojo<K, V, MODS, M>: Send + Sync,
+{
+}
+
+// Leaves the copy a reader registered on, even if the query panicked
+struct Registered<'a> {
+    state: &'a AtomicU64,
+    side: usize,
+}
+
+impl Drop for Registered<'_> {
+    fn drop(&mut self) {
+        self.state.fetch_sub(READER[self.side], Ordering::Release);
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Shared<K, V, MODS, M> {
+    fn new(gojo: Gojo<K, V, MODS, M>) -> Shared<K, V, MODS, M>
+    where
+        Gojo<K, V, MODS, M>: Clone,
+    {
+        let latest = gojo.latest_version();
+        Shared {
+            trees: [UnsafeCell::new(gojo.clone()), UnsafeCell::new(gojo)],
+            state: AtomicU64::new(0),
+            latest: AtomicUsize::new(latest),
+        }
+    }
+
+    fn latest(&self) -> usize {
+        self.latest.load(Ordering::Acquire)
+    }
+
+    // Runs `f` on the active copy. It holds every version published before
+    // the call, as the copy made active with them stays so until the other
+    // one has them too
+    fn read<R>(&self, f: impl FnOnce(&Gojo<K, V, MODS, M>) -> R) -> R {
+        let mut side = (self.state.load(Ordering::Acquire) & ACTIVE) as usize;
+        loop {
+            let state = self.state.fetch_add(READER[side], Ordering::AcqRel);
+            if (state & ACTIVE) as usize == side {
+                break;
+            }
+            // The writer switched copies in between, it may be changing this one
+            self.state.fetch_sub(READER[side], Ordering::Release);
+            side = 1 - side;
+        }
+        let _registered = Registered {
+            state: &self.state,
+            side,
+        };
+        // SAFETY: the writer waits for the registered readers to leave a
+        // copy before changing it, and this one was active when registered
+        self.trees[side].with(|tree| f(unsafe { &*tree }))
+    }
+
+    // Makes `f` on both copies, one after the other, publishing the versions
+    // it creates once the first one is done. Only the writer calls it
+    fn write<R>(&self, mut f: impl FnMut(&mut Gojo<K, V, MODS, M>) -> R) -> R {
+        let old = (self.state.load(Ordering::Acquire) & ACTIVE) as usize;
+        let new = 1 - old;
+        // SAFETY: readers left the inactive copy before the last update ended
+        let (result, latest) = self.trees[new].with_mut(|tree| {
+            let tree = unsafe { &mut *tree };
+            (f(tree), tree.latest_version())
+        });
+        self.state.fetch_xor(ACTIVE, Ordering::AcqRel);
+        self.latest.store(latest, Ordering::Release);
+
+        while self.state.load(Ordering::Acquire) & READERS[old] != 0 {
+            thread::yield_now();
+        }
+        // SAFETY: the copy is no longer active and its readers left
+        self.trees[old].with_mut(|tree| f(unsafe { &mut *tree }));
+        result
+    }
+}
+
+#[cfg(not(loom))]
+// The cell of `std`, with the interface of the one of loom
+struct UnsafeCell<T>(std::cell::UnsafeCell<T>);
+
+#[cfg(not(loom))]
+impl<T> UnsafeCell<T> {
+    fn new(value: T) -> UnsafeCell<T> {
+        UnsafeCell(std::cell::UnsafeCell::new(value))
+    }
+
+    fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+/// The only handle that updates a shared `Gojo`. It keeps two copies of the
+/// tree, so that readers never wait for an update: each update is made on the
+/// copy readers are not using, published, and made again on the other one once
+/// its readers left. That doubles the memory of the tree, and an update waits
+/// for the queries still running on the copy it has to catch up.
+///
+/// As every update runs twice, the writer only takes updates that can be
+/// repeated: `batch` takes a closure that may run more than once, and there is
+/// no `entry`, whose borrow of a single tree cannot be made on both.
+pub struct GojoWriter<K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()> {
+    shared: Arc<Shared<K, V, MODS, M>>,
+}
+
+/// A cloneable handle that queries the versions a `GojoWriter` published,
+/// from any thread, without waiting for the update in progress.
+pub struct GojoReader<K: Ord + Clone, V: Clone, const MODS: usize = 6, M: Monoid<K, V> = ()> {
+    shared: Arc<Shared<K, V, MODS, M>>,
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> Clone
+    for GojoReader<K, V, MODS, M>
+{
+    fn clone(&self) -> GojoReader<K, V, MODS, M> {
+        GojoReader {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> GojoWriter<K, V, MODS, M> {
+    /// Shares `gojo`, every version it already has is published.
+    pub fn new(gojo: Gojo<K, V, MODS, M>) -> GojoWriter<K, V, MODS, M>
+    where
+        Gojo<K, V, MODS, M>: Clone,
+    {
+        GojoWriter {
+            shared: Arc::new(Shared::new(gojo)),
+        }
+    }
+
+    pub fn reader(&self) -> GojoReader<K, V, MODS, M> {
+        GojoReader {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    pub fn latest_version(&self) -> usize {
+        self.shared.latest()
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> InsertOutcome {
+        self.shared.write(|gojo| gojo.insert(k.clone(), v.clone()))
+    }
+
+    pub fn insert_at(&mut self, version: usize, k: K, v: V) -> Result<InsertOutcome> {
+        self.shared
+            .write(|gojo| gojo.insert_at(version, k.clone(), v.clone()))
+    }
+
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        self.shared.write(|gojo| gojo.remove(k))
+    }
+
+    pub fn remove_at(&mut self, version: usize, k: &K) -> Result<Option<V>> {
+        self.shared.write(|gojo| gojo.remove_at(version, k))
+    }
+
+    /// Same as `Gojo::batch`. `f` runs once on each copy of the tree and
+    /// must record the same changes both times.
+    pub fn batch<F>(&mut self, mut f: F) -> Result<usize>
+    where
+        F: FnMut(&mut GojoTx<'_, K, V, MODS, M>) -> Result<()>,
+    {
+        self.shared.write(|gojo| gojo.batch(&mut f))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, const MODS: usize, M: Monoid<K, V>> GojoReader<K, V, MODS, M> {
+    /// Latest version published by the writer, newer ones may be on their way.
+    pub fn latest_version(&self) -> usize {
+        self.shared.latest()
+    }
+
+    /// Runs `f` on the tree, if `version` was already published.
+    pub fn read<R>(&self, version: usize, f: impl FnOnce(&Gojo<K, V, MODS, M>) -> R) -> Result<R> {
+        if version > self.shared.latest() {
+            anyhow::bail!(GojoError::UnknownVersion(format!("{version}")));
+        }
+
+        Ok(self.shared.read(f))
+    }
+
+    pub fn get(&self, k: &K, version: usize) -> Option<V> {
+        self.read(version, |gojo| gojo.get(k, version).cloned())
+            .ok()
+            .flatten()
+    }
+
+    pub fn contains_key(&self, k: &K, version: usize) -> bool {
+        self.read(version, |gojo| gojo.contains_key(k, version))
+            .unwrap_or(false)
+    }
+
+    pub fn len(&self, version: usize) -> Option<usize> {
+        self.read(version, |gojo| gojo.len(version)).ok().flatten()
+    }
+
+    pub fn is_empty(&self, version: usize) -> bool {
+        self.len(version) == Some(0)
+    }
+
+    /// Keys and values of `version`, in order.
+    pub fn to_vec(&self, version: usize) -> Result<Vec<(K, V)>> {
+        self.read(version, |gojo| {
+            Ok(gojo
+                .iter(version)?
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect())
+        })?
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod shared_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::GojoWriter;
+    use crate::gojo::{monoid::Sum, DuplicatePolicy, Gojo, GojoError};
+    use anyhow::Result;
+    use std::thread;
+
+    #[test]
+    fn test_readers_only_see_published_versions() -> Result<()> {
+        // Arrange
+        let mut writer = GojoWriter::new(Gojo::default());
+        let reader = writer.reader();
+
+        // Act
+        writer.insert(1, "one");
+        writer.insert(2, "two");
+        writer.remove(&1);
+
+        // Assert
+        assert_eq!(3, reader.latest_version());
+        assert_eq!(Some("one"), reader.get(&1, 2));
+        assert_eq!(None, reader.get(&1, 3));
+        assert_eq!(Some(1), reader.len(3));
+        assert_eq!(None, reader.len(4));
+        assert!(!reader.contains_key(&2, 4));
+        let err = reader.to_vec(4).unwrap_err().downcast::<GojoError>()?;
+        assert_eq!("the version `4` is not available", err.to_string());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_versions_of_a_shared_gojo_are_published() -> Result<()> {
+        // Arrange
+        let mut gojo = Gojo::default();
+        gojo.insert(1, 10);
+
+        // Act
+        let mut writer = GojoWriter::new(gojo);
+        let reader = writer.reader().clone();
+        writer.insert_at(0, 2, 20)?;
+
+        // Assert
+        assert_eq!(2, reader.latest_version());
+        assert_eq!(vec![(1, 10)], reader.to_vec(1)?);
+        assert_eq!(vec![(2, 20)], reader.to_vec(2)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_readers_do_not_wait_for_the_update_in_progress() -> Result<()> {
+        // Arrange
+        let mut writer = GojoWriter::new(Gojo::default());
+        writer.insert(1, 10);
+        let reader = writer.reader();
+
+        // Act
+        let version = writer.batch(|tx| {
+            // The query would never end if it waited for the batch
+            let seen = thread::scope(|scope| scope.spawn(|| reader.to_vec(1)).join().unwrap())?;
+            assert_eq!(vec![(1, 10)], seen);
+            tx.insert(2, 20);
+            tx.remove(&1);
+            Ok(())
+        })?;
+
+        // Assert
+        assert_eq!(2, version);
+        assert_eq!(2, reader.latest_version());
+        assert_eq!(vec![(2, 20)], reader.to_vec(2)?);
+        assert_eq!(vec![(1, 10)], reader.to_vec(1)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_share_a_gojo_with_copies_and_summaries() -> Result<()> {
+        // Arrange
+        let gojo: Gojo<i32, i32, 2, Sum> = Gojo::with_mods(10, DuplicatePolicy::Replace);
+        let mut writer = GojoWriter::new(gojo);
+        let reader = writer.reader();
+
+        // Act
+        for k in 0..20 {
+            writer.insert(k, k);
+        }
+        writer.insert_at(10, 5, 50)?;
+
+        // Assert
+        assert_eq!(21, reader.latest_version());
+        assert_eq!(190, reader.read(20, |gojo| gojo.aggregate(20, ..))??);
+        assert_eq!(90, reader.read(21, |gojo| gojo.aggregate(21, ..))??);
+        assert_eq!(Some(50), reader.get(&5, 21));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_readers_query_published_versions_while_writer_updates() {
+        // Arrange
+        const OPS: usize = 500;
+        let mut writer = GojoWriter::new(Gojo::default());
+        let readers: Vec<_> = (0..4).map(|_| writer.reader()).collect();
+
+        // Act
+        let handles: Vec<_> = readers
+            .into_iter()
+            .enumerate()
+            .map(|(i, reader)| {
+                thread::spawn(move || {
+                    let mut checked = 0;
+                    loop {
+                        let latest = reader.latest_version();
+                        // Version `v` holds the keys `0..v`, whenever it is read
+                        let version = (latest + i * 7) % (latest + 1);
+                        let keys: Vec<usize> = reader
+                            .to_vec(version)
+                            .unwrap()
+                            .into_iter()
+                            .map(|(k, _)| k)
+                            .collect();
+                        assert_eq!((0..version).collect::<Vec<_>>(), keys);
+                        checked += 1;
+                        if latest == OPS {
+                            return checked;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for k in 0..OPS {
+            if k % 2 == 0 {
+                writer.insert(k, k);
+            } else {
+                // Branching from the latest version is the same as `insert`
+                writer.insert_at(k, k, k).unwrap();
+            }
+        }
+
+        // Assert
+        for handle in handles {
+            assert!(handle.join().unwrap() > 0);
+        }
+        assert_eq!(OPS, writer.latest_version());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod shared_loom_tests {
+    use loom::thread;
+
+    use super::GojoWriter;
+    use crate::gojo::{DuplicatePolicy, Gojo};
+
+    #[test]
+    fn test_readers_only_see_whole_published_versions() {
+        let mut model = loom::model::Builder::new();
+        model.preemption_bound = Some(3);
+        model.check(|| {
+            // Arrange
+            let gojo: Gojo<i32, i32> = Gojo::with_policy(4, DuplicatePolicy::Replace);
+            let mut writer = GojoWriter::new(gojo);
+            let readers: Vec<_> = (0..2).map(|_| writer.reader()).collect();
+
+            // Act
+            let handles: Vec<_> = readers
+                .into_iter()
+                .map(|reader| {
+                    thread::spawn(move || {
+                        let latest = reader.latest_version();
+                        let keys: Vec<i32> = reader
+                            .to_vec(latest)
+                            .unwrap()
+                            .into_iter()
+                            .map(|(k, _)| k)
+                            .collect();
+                        (latest, keys)
+                    })
+                })
+                .collect();
+            writer.insert(1, 10);
+            writer.insert(2, 20);
+
+            // Assert
+            for handle in handles {
+                // Version `v` holds the keys `1..=v`
+                let (latest, keys) = handle.join().unwrap();
+                assert_eq!((1..=latest as i32).collect::<Vec<_>>(), keys);
+            }
+            assert_eq!(Some(20), writer.reader().get(&2, 2));
+        });
+    }
+}
@@ -0,0 +1,364 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::mem;
+
+const LABEL_STEP: u64 = 1 << 32;
+// Overflow threshold of the relabeled ranges: a range of `2^i` labels takes at
+// most `(2 / T)^i` versions
+const THRESHOLD: f64 = 1.3;
+
+/// Order-maintenance list over the versions of a `Gojo`.
+///
+/// Versions form a tree: every new version is derived from some older one.
+/// The list keeps them in the order a fully persistent fat node needs, where a
+/// child version is placed right after its parent, so the descendants of a
+/// version always sit in a contiguous run right after it.
+///
+/// Labels are kept as in the list labeling of Bender et al.: when there is no
+/// room left after a version, only the smallest range of labels around it
+/// that is sparse enough is spread evenly again, which costs `O(log n)`
+/// relabeled versions amortized per insert.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct VersionList {
+    first: usize,
+    head: usize,
+    labels: Vec<u64>,
+    next: Vec<Option<usize>>,
+    prev: Vec<Option<usize>>,
+}
+
+impl VersionList {
+    /// Creates a list holding only version `0`.
+    pub(crate) fn new(predected_amount_of_versions: usize) -> VersionList {
+        let mut labels = Vec::with_capacity(predected_amount_of_versions);
+        let mut next = Vec::with_capacity(predected_amount_of_versions);
+        let mut prev = Vec::with_capacity(predected_amount_of_versions);
+        labels.push(0);
+        next.push(None);
+        prev.push(None);
+        VersionList {
+            first: 0,
+            head: 0,
+            labels,
+            next,
+            prev,
+        }
+    }
+
+    /// Inserts a new version right after `version`. Returns its id, with the
+    /// versions relabeled to make room for it and the labels they had before.
+    pub(crate) fn insert_after(&mut self, version: usize) -> (usize, Vec<(usize, u64)>) {
+        let mut relabeled = Vec::new();
+        let label = match self.free_label_after(version) {
+            Some(label) => label,
+            None => {
+                relabeled = self.relabel_around(version);
+                self.free_label_after(version)
+                    .expect("relabel always leaves a gap after the version")
+            }
+        };
+
+        let id = self.first + self.labels.len();
+        let after = self.next[version - self.first];
+        self.labels.push(label);
+        self.next.push(after);
+        self.prev.push(Some(version));
+        self.next[version - self.first] = Some(id);
+        if let Some(after) = after {
+            self.prev[after - self.first] = Some(id);
+        }
+        (id, relabeled)
+    }
+
+    /// Label of `version`, giving its position in the list.
+    pub(crate) fn label(&self, version: usize) -> u64 {
+        self.labels[version - self.first]
+    }
+
+    /// Version right after `version` in the list, if any.
+    pub(crate) fn next(&self, version: usize) -> Option<usize> {
+        self.next[version - self.first]
+    }
+
+    /// Compares the position of two versions in the list.
+    pub(crate) fn cmp(&self, a: usize, b: usize) -> Ordering {
+        self.labels[a - self.first].cmp(&self.labels[b - self.first])
+    }
+
+    /// Whether `version` is in the list.
+    #[cfg(feature = "serde")]
+    pub(crate) fn contains(&self, version: usize) -> bool {
+        version >= self.first && version - self.first < self.labels.len()
+    }
+
+    /// Checks a list read back from somewhere else: walking it from its head
+    /// goes through every version once, in label order.
+    #[cfg(feature = "serde")]
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.labels.len() != self.next.len()
+            || self.labels.len() != self.prev.len()
+            || !self.contains(self.head)
+        {
+            return Err("version list out of bounds".to_string());
+        }
+        let mut seen = 0;
+        let mut prev: Option<usize> = None;
+        let mut curr = Some(self.head);
+        while let Some(version) = curr {
+            if !self.contains(version) || seen == self.labels.len() {
+                return Err(format!("version list broken at version {version}"));
+            }
+            if prev.is_some_and(|prev| self.cmp(prev, version) != Ordering::Less) {
+                return Err(format!("version {version}: label out of order"));
+            }
+            if self.prev[version - self.first] != prev {
+                return Err(format!("version {version}: wrong previous version"));
+            }
+            seen += 1;
+            prev = Some(version);
+            curr = self.next(version);
+        }
+        match seen == self.labels.len() {
+            true => Ok(()),
+            false => Err(format!(
+                "{seen} versions listed out of {}",
+                self.labels.len()
+            )),
+        }
+    }
+
+    /// Oldest version still in the list.
+    pub(crate) fn first(&self) -> usize {
+        self.first
+    }
+
+    /// Drops every version before `keep_from` from the list. Returns, for each
+    /// dropped version, the first kept one placed after it, if any.
+    pub(crate) fn truncate(&mut self, keep_from: usize) -> Vec<Option<usize>> {
+        let mut order = Vec::with_capacity(self.labels.len());
+        let mut curr = Some(self.head);
+        while let Some(version) = curr {
+            order.push(version);
+            curr = self.next(version);
+        }
+
+        let mut moved_to = vec![None; keep_from - self.first];
+        let mut next_kept = None;
+        for &version in order.iter().rev() {
+            if version < keep_from {
+                moved_to[version - self.first] = next_kept;
+                continue;
+            }
+            self.next[version - self.first] = next_kept;
+            if let Some(next) = next_kept {
+                self.prev[next - self.first] = Some(version);
+            }
+            next_kept = Some(version);
+        }
+
+        self.head = next_kept.expect("the latest version is always kept");
+        self.prev[self.head - self.first] = None;
+        self.labels.drain(..keep_from - self.first);
+        self.next.drain(..keep_from - self.first);
+        self.prev.drain(..keep_from - self.first);
+        self.first = keep_from;
+        moved_to
+    }
+
+    fn free_label_after(&self, version: usize) -> Option<u64> {
+        let low = self.label(version);
+        let high = match self.next(version) {
+            Some(next) => self.label(next) as u128,
+            None if low.checked_add(LABEL_STEP).is_some() => return Some(low + LABEL_STEP),
+            None => 1 << 64,
+        };
+        match high - (low as u128) < 2 {
+            true => None,
+            false => Some(low + ((high - low as u128) / 2) as u64),
+        }
+    }
+
+    // Spreads evenly the labels of the smallest aligned range around the one
+    // of `version` holding few enough versions, the sparser the larger it is.
+    // Returns the versions relabeled with their old labels
+    fn relabel_around(&mut self, version: usize) -> Vec<(usize, u64)> {
+        let label = self.label(version) as u128;
+        // Versions of the range, found by walking away from `version`
+        let (mut first, mut last, mut count) = (version, version, 1u128);
+        let mut size: u128 = 1;
+        let mut limit = 1.0;
+        loop {
+            size <<= 1;
+            limit *= 2.0 / THRESHOLD;
+            let base = label & !(size - 1);
+            while let Some(before) = self.prev[first - self.first] {
+                if (self.label(before) as u128) < base {
+                    break;
+                }
+                (first, count) = (before, count + 1);
+            }
+            while let Some(after) = self.next(last) {
+                if self.label(after) as u128 >= base + size {
+                    break;
+                }
+                (last, count) = (after, count + 1);
+            }
+            // Room for one more version, with gaps of two labels at least
+            let sparse = (count + 1) as f64 <= limit && 2 * count <= size;
+            if sparse || size == 1 << 64 {
+                return self.spread(first, count, base, size);
+            }
+        }
+    }
+
+    // Gives `count` versions from `first` on labels evenly spread over the
+    // range of `size` labels from `base`
+    fn spread(&mut self, first: usize, count: u128, base: u128, size: u128) -> Vec<(usize, u64)> {
+        let step = size / count;
+        let mut relabeled = Vec::with_capacity(count as usize);
+        let mut curr = Some(first);
+        for at in 0..count {
+            let version = curr.expect("the range holds `count` versions");
+            let label = (base + at * step) as u64;
+            relabeled.push((
+                version,
+                mem::replace(&mut self.labels[version - self.first], label),
+            ));
+            curr = self.next(version);
+        }
+        relabeled
+    }
+}
+
+#[cfg(test)]
+mod version_list_tests {
+    use pretty_assertions::assert_eq;
+    use std::cmp::Ordering;
+
+    use super::VersionList;
+
+    #[test]
+    fn test_linear_versions_are_ordered_by_id() {
+        // Arrange
+        let mut list = VersionList::new(10);
+
+        // Act
+        for version in 0..9 {
+            list.insert_after(version);
+        }
+
+        // Assert
+        for version in 0..9 {
+            assert_eq!(Ordering::Less, list.cmp(version, version + 1));
+            assert_eq!(Some(version + 1), list.next(version));
+        }
+        assert_eq!(None, list.next(9));
+    }
+
+    #[test]
+    fn test_child_is_placed_right_after_parent() {
+        // Arrange
+        let mut list = VersionList::new(10);
+        let one = list.insert_after(0).0;
+        let two = list.insert_after(one).0;
+
+        // Act
+        let branch = list.insert_after(0).0;
+
+        // Assert
+        assert_eq!(Some(branch), list.next(0));
+        assert_eq!(Some(one), list.next(branch));
+        assert_eq!(Ordering::Less, list.cmp(branch, one));
+        assert_eq!(Ordering::Less, list.cmp(branch, two));
+        assert_eq!(Ordering::Greater, list.cmp(branch, 0));
+    }
+
+    #[test]
+    fn test_relabel_keeps_order_when_gaps_run_out() {
+        // Arrange
+        let mut list = VersionList::new(100);
+        let last = list.insert_after(0).0;
+        let mut expected = vec![0];
+
+        // Act
+        for _ in 0..80 {
+            let version = list.insert_after(0).0;
+            expected.insert(1, version);
+        }
+        expected.push(last);
+
+        // Assert
+        for pair in expected.windows(2) {
+            assert_eq!(Ordering::Less, list.cmp(pair[0], pair[1]));
+            assert_eq!(Some(pair[1]), list.next(pair[0]));
+        }
+    }
+
+    #[test]
+    fn test_relabel_stays_local_when_inserting_after_one_version() {
+        // Arrange
+        let mut list = VersionList::new(20_010);
+        for version in 0..10 {
+            list.insert_after(version);
+        }
+        let mut expected: Vec<usize> = (0..=10).collect();
+
+        // Act
+        let mut relabeled = 0;
+        for _ in 0..20_000 {
+            let (version, moved) = list.insert_after(5);
+            relabeled += moved.len();
+            expected.insert(6, version);
+        }
+
+        // Assert
+        assert!(relabeled < 40 * 20_000, "{relabeled} versions relabeled");
+        for pair in expected.windows(2) {
+            assert_eq!(Ordering::Less, list.cmp(pair[0], pair[1]));
+            assert_eq!(Some(pair[1]), list.next(pair[0]));
+        }
+    }
+
+    #[test]
+    fn test_truncate_moves_dropped_versions_to_next_kept() {
+        // Arrange
+        let mut list = VersionList::new(10);
+        let one = list.insert_after(0).0;
+        let two = list.insert_after(one).0;
+        let branch = list.insert_after(0).0;
+        let three = list.insert_after(two).0;
+
+        // Act
+        let moved_to = list.truncate(branch);
+
+        // Assert
+        assert_eq!(vec![Some(branch), Some(three), Some(three)], moved_to);
+        assert_eq!(Some(three), list.next(branch));
+        assert_eq!(None, list.next(three));
+        assert_eq!(Ordering::Less, list.cmp(branch, three));
+    }
+
+    #[test]
+    fn test_insert_after_truncate() {
+        // Arrange
+        let mut list = VersionList::new(10);
+        for version in 0..5 {
+            list.insert_after(version);
+        }
+        list.truncate(3);
+
+        // Act
+        let version = list.insert_after(3).0;
+        for _ in 0..80 {
+            list.insert_after(3);
+        }
+
+        // Assert
+        assert_eq!(6, version);
+        assert_eq!(Ordering::Less, list.cmp(3, version));
+        assert_eq!(Ordering::Less, list.cmp(version, 4));
+        assert_eq!(Some(4), list.next(version));
+    }
+}
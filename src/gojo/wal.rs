@@ -0,0 +1,814 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use super::{DuplicatePolicy, Gojo, GojoError, InsertOutcome, NodePtr};
+
+// Every record is `len: u32 | crc32 of payload: u32 | payload`, little endian
+const HEADER_LEN: u64 = 8;
+
+/// When the log asks the OS to flush its records to disk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// After every record, nothing acknowledged is lost on a crash.
+    Always,
+    /// After every `n` records.
+    EveryN(usize),
+    /// Left to the OS, records survive a crash of the process but not of the
+    /// machine.
+    Never,
+}
+
+// One change to the tree, each update created exactly one version when it was
+// applied. They do not depend on the duplicate policy, that only decides which
+// one an update makes
+#[derive(Serialize, Deserialize)]
+enum Record<K, V> {
+    Insert { from: usize, key: K, value: V },
+    Replace { from: usize, key: K, value: V },
+    Count { from: usize, key: K, count: usize },
+    Remove { from: usize, key: K },
+    Truncate { keep_from: usize },
+    Label { version: usize, label: String },
+    Stamp { version: usize, timestamp: u64 },
+    // A version equal to `from`, made by `college_remove` of a missing key
+    Unchanged { from: usize },
+}
+
+// What the log needs from the file it appends to
+trait LogFile: Write {
+    fn len(&self) -> io::Result<u64>;
+    fn set_len(&self, len: u64) -> io::Result<()>;
+    fn sync_data(&self) -> io::Result<()>;
+}
+
+impl LogFile for File {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
+
+struct Wal<F: LogFile = File> {
+    file: F,
+    policy: SyncPolicy,
+    unsynced: usize,
+    // End of the last record fully appended
+    len: u64,
+    // Set when a torn record could not be cut off the file
+    failed: bool,
+}
+
+impl<F: LogFile> Wal<F> {
+    fn new(file: F, policy: SyncPolicy) -> Result<Wal<F>> {
+        let len = file.len()?;
+        Ok(Wal {
+            file,
+            policy,
+            unsynced: 0,
+            len,
+            failed: false,
+        })
+    }
+
+    /// Appends `record`. When writing or syncing it fails, it is cut off the
+    /// file, so no torn record is left before the ones appended next. If it
+    /// cannot be, the log takes no more records.
+    fn append<K: Serialize, V: Serialize>(&mut self, record: &Record<&K, &V>) -> Result<()> {
+        if self.failed {
+            anyhow::bail!(GojoError::FailedLog);
+        }
+        let payload = bincode::serialize(record)?;
+        let mut bytes = Vec::with_capacity(HEADER_LEN as usize + payload.len());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        if let Err(err) = self.write(&bytes) {
+            if self.file.set_len(self.len).is_err() {
+                self.failed = true;
+            }
+            return Err(err);
+        }
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.file.write_all(bytes)?;
+        let unsynced = self.unsynced + 1;
+        let should_sync = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::EveryN(n) => unsynced >= n,
+            SyncPolicy::Never => false,
+        };
+        match should_sync {
+            true => self.sync(),
+            false => {
+                self.unsynced = unsynced;
+                Ok(())
+            }
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+/// A `Gojo` that appends every update to a write-ahead log before applying
+/// it, so it can be rebuilt with all its versions by `Gojo::recover`. An
+/// update whose record fails to be appended is not applied.
+pub struct WalGojo<K: Ord + Clone, V: Clone> {
+    gojo: Gojo<K, V>,
+    wal: Wal,
+}
+
+impl<K, V> WalGojo<K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Recovers the tree logged at `path`, or starts an empty one if there is
+    /// no log yet, and keeps appending to it.
+    pub fn open(path: impl AsRef<Path>, policy: SyncPolicy) -> Result<WalGojo<K, V>> {
+        Self::open_with_policy(path, policy, DuplicatePolicy::default())
+    }
+
+    /// Same as `open`, handling keys inserted again from now on as
+    /// `duplicates` says.
+    pub fn open_with_policy(
+        path: impl AsRef<Path>,
+        policy: SyncPolicy,
+        duplicates: DuplicatePolicy,
+    ) -> Result<WalGojo<K, V>> {
+        let path = path.as_ref();
+        let mut gojo = match path.exists() {
+            true => Gojo::recover(path)?,
+            false => Gojo::default(),
+        };
+        gojo.policy = duplicates;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(WalGojo {
+            gojo,
+            wal: Wal::new(file, policy)?,
+        })
+    }
+
+    /// The tree, to be queried on any of its versions.
+    pub fn gojo(&self) -> &Gojo<K, V> {
+        &self.gojo
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Result<InsertOutcome> {
+        self.insert_at(self.gojo.curr_version, k, v)
+    }
+
+    pub fn insert_at(&mut self, version: usize, k: K, v: V) -> Result<InsertOutcome> {
+        self.gojo.root_entry(version)?;
+        let node = self.gojo.find_node(&k, version);
+        if node.is_null() {
+            self.wal.append(&Record::Insert {
+                from: version,
+                key: &k,
+                value: &v,
+            })?;
+            self.gojo.insert_node(version, k, v);
+            return Ok(InsertOutcome::Inserted);
+        }
+
+        match self.gojo.policy {
+            DuplicatePolicy::Reject => Ok(InsertOutcome::Rejected),
+            DuplicatePolicy::Replace => {
+                self.wal.append(&Record::Replace {
+                    from: version,
+                    key: &k,
+                    value: &v,
+                })?;
+                self.gojo.replace_value(version, node, v);
+                Ok(InsertOutcome::Replaced)
+            }
+            DuplicatePolicy::Multi => {
+                let count = self.gojo.arena.count(node, version) + 1;
+                self.wal.append(&Record::<_, &V>::Count {
+                    from: version,
+                    key: &k,
+                    count,
+                })?;
+                self.gojo.recount(version, node, count);
+                Ok(InsertOutcome::Counted)
+            }
+        }
+    }
+
+    pub fn insert_or_replace(&mut self, k: K, v: V) -> Result<Option<V>> {
+        let from = self.gojo.curr_version;
+        let node = self.gojo.find_node(&k, from);
+        if node.is_null() {
+            self.insert_at(from, k, v)?;
+            return Ok(None);
+        }
+
+        self.wal.append(&Record::Replace {
+            from,
+            key: &k,
+            value: &v,
+        })?;
+        let old = self.gojo.arena.value_at(node, from).clone();
+        self.gojo.replace_value(from, node, v);
+        Ok(Some(old))
+    }
+
+    pub fn update<F: FnOnce(&V) -> V>(&mut self, k: &K, f: F) -> Result<bool> {
+        let from = self.gojo.curr_version;
+        let node = self.gojo.find_node(k, from);
+        if node.is_null() {
+            return Ok(false);
+        }
+
+        let value = f(self.gojo.arena.value_at(node, from));
+        self.wal.append(&Record::Replace {
+            from,
+            key: k,
+            value: &value,
+        })?;
+        self.gojo.replace_value(from, node, value);
+        Ok(true)
+    }
+
+    pub fn remove(&mut self, k: &K) -> Result<Option<V>> {
+        self.remove_at(self.gojo.curr_version, k)
+    }
+
+    pub fn remove_at(&mut self, version: usize, k: &K) -> Result<Option<V>> {
+        self.gojo.root_entry(version)?;
+        let node = self.gojo.find_node(k, version);
+        if node.is_null() {
+            return Ok(None);
+        }
+
+        let count = self.gojo.arena.count(node, version);
+        if count > 1 {
+            self.wal.append(&Record::<_, &V>::Count {
+                from: version,
+                key: k,
+                count: count - 1,
+            })?;
+            let value = self.gojo.arena.value_at(node, version).clone();
+            self.gojo.recount(version, node, count - 1);
+            return Ok(Some(value));
+        }
+
+        self.wal.append(&Record::<_, &V>::Remove {
+            from: version,
+            key: k,
+        })?;
+        Ok(Some(self.gojo.remove_node(version, node)))
+    }
+
+    pub fn college_remove(&mut self, k: &K) -> Result<Option<V>> {
+        let from = self.gojo.curr_version;
+        if !self.gojo.find_node(k, from).is_null() {
+            return self.remove_at(from, k);
+        }
+
+        self.wal.append(&Record::<&K, &V>::Unchanged { from })?;
+        self.gojo.unchanged_version(from);
+        Ok(None)
+    }
+
+    pub fn truncate_history(&mut self, keep_from: usize) -> Result<()> {
+        if keep_from > self.gojo.latest_version() {
+            anyhow::bail!(GojoError::UnknownVersion(format!("{keep_from}")));
+        }
+        if keep_from <= self.gojo.first_version {
+            return Ok(());
+        }
+
+        self.wal.append(&Record::<&K, &V>::Truncate { keep_from })?;
+        self.gojo.truncate_history(keep_from)
+    }
+
+    // Labels and timestamps are checked by setting them before they are logged
+
+    pub fn label_version(&mut self, version: usize, label: &str) -> Result<()> {
+        self.gojo.label_version(version, label)?;
+        self.wal.append(&Record::<&K, &V>::Label {
+            version,
+            label: label.to_string(),
+        })
+    }
+
+    pub fn stamp_version(&mut self, version: usize, timestamp: u64) -> Result<()> {
+        self.gojo.stamp_version(version, timestamp)?;
+        self.wal
+            .append(&Record::<&K, &V>::Stamp { version, timestamp })
+    }
+
+    /// Flushes to disk every record the sync policy has not flushed yet.
+    pub fn sync(&mut self) -> Result<()> {
+        self.wal.sync()
+    }
+}
+
+impl<K, V> Gojo<K, V>
+where
+    K: Ord + Clone + DeserializeOwned,
+    V: Clone + DeserializeOwned,
+{
+    /// Rebuilds, with all its versions, the tree logged at `path` by a
+    /// `WalGojo`. A torn record at the end of the log, left by a crash in the
+    /// middle of a write, is cut off the file. The duplicate policy is not in
+    /// the log, the tree gets the default one.
+    pub fn recover(path: impl AsRef<Path>) -> Result<Gojo<K, V>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(&file);
+        let mut gojo = Gojo::default();
+        let mut offset = 0;
+
+        while offset + HEADER_LEN <= file_len {
+            let mut header = [0; HEADER_LEN as usize];
+            reader.read_exact(&mut header)?;
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let checksum = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let end = offset + HEADER_LEN + len as u64;
+            if end > file_len {
+                break;
+            }
+
+            let mut payload = vec![0; len as usize];
+            reader.read_exact(&mut payload)?;
+            if crc32(&payload) != checksum {
+                if end == file_len {
+                    break;
+                }
+                anyhow::bail!(GojoError::CorruptedLog(format!("{offset}")));
+            }
+
+            let record = bincode::deserialize(&payload)
+                .map_err(|_| GojoError::CorruptedLog(format!("{offset}")))?;
+            gojo.replay(record, offset)?;
+            offset = end;
+        }
+
+        if offset < file_len {
+            file.set_len(offset)?;
+            file.sync_all()?;
+        }
+        Ok(gojo)
+    }
+
+    // Applies the record at `offset` of the log, a record that does not match
+    // the tree it was logged for means the log is corrupted
+    fn replay(&mut self, record: Record<K, V>, offset: u64) -> Result<()> {
+        match record {
+            Record::Insert { from, key, value } => {
+                self.root_entry(from)?;
+                if !self.find_node(&key, from).is_null() {
+                    anyhow::bail!(GojoError::CorruptedLog(format!("{offset}")));
+                }
+                self.insert_node(from, key, value);
+            }
+            Record::Replace { from, key, value } => {
+                let node = self.replayed_node(from, &key, offset)?;
+                self.replace_value(from, node, value);
+            }
+            Record::Count { from, key, count } => {
+                let node = self.replayed_node(from, &key, offset)?;
+                self.recount(from, node, count);
+            }
+            Record::Remove { from, key } => {
+                let node = self.replayed_node(from, &key, offset)?;
+                self.remove_node(from, node);
+            }
+            Record::Unchanged { from } => {
+                self.root_entry(from)?;
+                self.unchanged_version(from);
+            }
+            Record::Truncate { keep_from } => self.truncate_history(keep_from)?,
+            Record::Label { version, label } => self.label_version(version, &label)?,
+            Record::Stamp { version, timestamp } => self.stamp_version(version, timestamp)?,
+        }
+        Ok(())
+    }
+
+    // Node of `k` at `from`, that a record changing it expects to be there
+    fn replayed_node(&self, from: usize, k: &K, offset: u64) -> Result<NodePtr> {
+        self.root_entry(from)?;
+        let node = self.find_node(k, from);
+        if node.is_null() {
+            anyhow::bail!(GojoError::CorruptedLog(format!("{offset}")));
+        }
+        Ok(node)
+    }
+}
+
+// CRC-32 (IEEE), the one used by zlib and png
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod wal_tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+    use std::cell::{Cell, RefCell};
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+
+    use super::{crc32, LogFile, Record, SyncPolicy, Wal, WalGojo};
+    use crate::gojo::{DuplicatePolicy, Gojo, GojoError, InsertOutcome};
+
+    fn log_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("hokkaido_wal_{}_{name}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    // File in memory that fails once it has taken `budget` more bytes, or
+    // when syncing or cutting it off if asked to
+    #[derive(Default)]
+    struct FailingFile {
+        bytes: RefCell<Vec<u8>>,
+        budget: Cell<usize>,
+        fail_sync: Cell<bool>,
+        fail_set_len: Cell<bool>,
+    }
+
+    impl Write for FailingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let taken = buf.len().min(self.budget.get());
+            if taken == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            self.budget.set(self.budget.get() - taken);
+            self.bytes.borrow_mut().extend_from_slice(&buf[..taken]);
+            Ok(taken)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogFile for FailingFile {
+        fn len(&self) -> io::Result<u64> {
+            Ok(self.bytes.borrow().len() as u64)
+        }
+
+        fn set_len(&self, len: u64) -> io::Result<()> {
+            if self.fail_set_len.get() {
+                return Err(io::Error::other("cannot truncate"));
+            }
+            self.bytes.borrow_mut().truncate(len as usize);
+            Ok(())
+        }
+
+        fn sync_data(&self) -> io::Result<()> {
+            match self.fail_sync.get() {
+                true => Err(io::Error::other("cannot sync")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    type Versions = Vec<Vec<(i32, i32)>>;
+
+    fn every_version(gojo: &Gojo<i32, i32>) -> Versions {
+        (gojo.first_version..=gojo.latest_version())
+            .map(|version| gojo.iter(version).unwrap().map(|(k, v)| (*k, *v)).collect())
+            .collect()
+    }
+
+    fn write_log(name: &str) -> Result<(PathBuf, Versions)> {
+        let path = log_path(name);
+        let mut gojo = WalGojo::open(&path, SyncPolicy::Always)?;
+        for key in 0..10 {
+            gojo.insert(key, key * 10)?;
+        }
+        gojo.remove(&3)?;
+        gojo.insert_at(4, 42, 420)?;
+        Ok((path, every_version(gojo.gojo())))
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        // Act
+        let crc = crc32(b"123456789");
+
+        // Assert
+        assert_eq!(0xCBF4_3926, crc);
+    }
+
+    #[test]
+    fn test_recover_rebuilds_every_version() -> Result<()> {
+        // Arrange
+        let path = log_path("every_version");
+        let mut gojo = WalGojo::open(&path, SyncPolicy::EveryN(4))?;
+        for key in [5, 1, 9, 3, 7, 2, 8] {
+            gojo.insert(key, key)?;
+        }
+        gojo.remove(&9)?;
+        assert_eq!(None, gojo.remove(&9)?);
+        gojo.college_remove(&9)?;
+        gojo.insert_or_replace(1, 100)?;
+        gojo.update(&2, |v| v + 1)?;
+        gojo.remove_at(3, &1)?;
+        gojo.insert_at(6, 4, 4)?;
+        gojo.truncate_history(2)?;
+        gojo.insert(6, 6)?;
+        gojo.label_version(13, "branch")?;
+        gojo.stamp_version(12, 1_000)?;
+        gojo.stamp_version(14, 2_000)?;
+        gojo.sync()?;
+        let expected = every_version(gojo.gojo());
+        let latest = gojo.gojo().latest_version();
+        drop(gojo);
+
+        // Act
+        let recovered: Gojo<i32, i32> = Gojo::recover(&path)?;
+
+        // Assert
+        assert_eq!(latest, recovered.latest_version());
+        assert_eq!(expected, every_version(&recovered));
+        assert_eq!(Some(6), recovered.parent_version(latest - 1));
+        assert_eq!(Some(13), recovered.version_by_label("branch"));
+        assert_eq!(Some(12), recovered.version_at_or_before(1_999));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_replays_duplicate_policies() -> Result<()> {
+        // Arrange
+        let path = log_path("duplicates");
+        let mut gojo = WalGojo::open_with_policy(&path, SyncPolicy::Never, DuplicatePolicy::Multi)?;
+        gojo.insert(1, 10)?;
+        gojo.insert(1, 10)?;
+        gojo.insert(2, 20)?;
+        gojo.remove(&1)?;
+        gojo.remove(&1)?;
+        gojo.insert(2, 20)?;
+        gojo.sync()?;
+        drop(gojo);
+        let mut gojo =
+            WalGojo::open_with_policy(&path, SyncPolicy::Never, DuplicatePolicy::Reject)?;
+        let rejected = gojo.insert(2, 21)?;
+        drop(gojo);
+
+        // Act
+        let recovered: Gojo<i32, i32> = Gojo::recover(&path)?;
+
+        // Assert
+        assert_eq!(InsertOutcome::Rejected, rejected);
+        assert_eq!(6, recovered.latest_version());
+        assert_eq!(2, recovered.count(&1, 2));
+        assert_eq!(1, recovered.count(&1, 4));
+        assert_eq!(0, recovered.count(&1, 5));
+        assert_eq!(2, recovered.count(&2, 6));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_truncates_torn_tail() -> Result<()> {
+        // Arrange
+        let (path, expected) = write_log("torn_tail")?;
+        let len = fs::metadata(&path)?.len();
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&[30, 0, 0, 0, 1, 2, 3, 4, 5, 6])?;
+
+        // Act
+        let recovered: Gojo<i32, i32> = Gojo::recover(&path)?;
+
+        // Assert
+        assert_eq!(expected, every_version(&recovered));
+        assert_eq!(len, fs::metadata(&path)?.len());
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_truncates_tail_with_bad_checksum() -> Result<()> {
+        // Arrange
+        let (path, mut expected) = write_log("bad_checksum")?;
+        let mut bytes = fs::read(&path)?;
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(&path, bytes)?;
+        expected.pop();
+
+        // Act
+        let recovered: Gojo<i32, i32> = Gojo::recover(&path)?;
+
+        // Assert
+        assert_eq!(expected, every_version(&recovered));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_rejects_corrupted_record_before_tail() -> Result<()> {
+        // Arrange
+        let (path, _) = write_log("corrupted")?;
+        let mut bytes = fs::read(&path)?;
+        bytes[8] ^= 0xFF;
+        fs::write(&path, bytes)?;
+
+        // Act
+        let result = Gojo::<i32, i32>::recover(&path);
+
+        // Assert
+        let error = result.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<GojoError>(),
+            Some(GojoError::CorruptedLog(_))
+        ));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_rejects_record_that_does_not_match_the_tree() -> Result<()> {
+        // Arrange
+        let (path, _) = write_log("mismatch")?;
+        let offset = fs::metadata(&path)?.len();
+        let mut wal = Wal::new(
+            OpenOptions::new().append(true).open(&path)?,
+            SyncPolicy::Always,
+        )?;
+        wal.append(&Record::Replace {
+            from: 11,
+            key: &3,
+            value: &30,
+        })?;
+        wal.append(&Record::<_, &i32>::Remove { from: 12, key: &4 })?;
+
+        // Act
+        let result = Gojo::<i32, i32>::recover(&path);
+
+        // Assert
+        let error = result.err().unwrap().downcast::<GojoError>()?;
+        assert!(matches!(error, GojoError::CorruptedLog(_)));
+        assert_eq!(
+            format!("the write-ahead log is corrupted at byte `{offset}`"),
+            error.to_string()
+        );
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_rejects_remove_of_missing_key() -> Result<()> {
+        // Arrange
+        let (path, _) = write_log("missing_remove")?;
+        let offset = fs::metadata(&path)?.len();
+        let mut wal = Wal::new(
+            OpenOptions::new().append(true).open(&path)?,
+            SyncPolicy::Always,
+        )?;
+        wal.append(&Record::<_, &i32>::Remove { from: 11, key: &3 })?;
+
+        // Act
+        let result = Gojo::<i32, i32>::recover(&path);
+
+        // Assert
+        let error = result.err().unwrap().downcast::<GojoError>()?;
+        assert_eq!(
+            format!("the write-ahead log is corrupted at byte `{offset}`"),
+            error.to_string()
+        );
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_rejects_record_that_cannot_be_decoded() -> Result<()> {
+        // Arrange
+        let (path, _) = write_log("undecodable")?;
+        let offset = fs::metadata(&path)?.len();
+        let payload = [99, 0, 0, 0];
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&(payload.len() as u32).to_le_bytes())?;
+        file.write_all(&crc32(&payload).to_le_bytes())?;
+        file.write_all(&payload)?;
+
+        // Act
+        let result = Gojo::<i32, i32>::recover(&path);
+
+        // Assert
+        let error = result.err().unwrap().downcast::<GojoError>()?;
+        assert_eq!(
+            format!("the write-ahead log is corrupted at byte `{offset}`"),
+            error.to_string()
+        );
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_cuts_off_record_that_failed_to_be_written() -> Result<()> {
+        // Arrange
+        let mut wal = Wal::new(FailingFile::default(), SyncPolicy::Always)?;
+        wal.file.budget.set(usize::MAX);
+        wal.append(&Record::Insert {
+            from: 0,
+            key: &1,
+            value: &10,
+        })?;
+        let good = wal.file.bytes.borrow().clone();
+
+        // Act
+        wal.file.budget.set(5);
+        let torn = wal.append(&Record::Insert {
+            from: 1,
+            key: &2,
+            value: &20,
+        });
+        wal.file.budget.set(usize::MAX);
+        wal.file.fail_sync.set(true);
+        let unsynced = wal.append(&Record::Insert {
+            from: 1,
+            key: &3,
+            value: &30,
+        });
+        let after_failures = (wal.file.bytes.borrow().clone(), wal.len);
+        wal.file.fail_sync.set(false);
+        let remove = Record::<_, &i32>::Remove { from: 1, key: &1 };
+        wal.append(&remove)?;
+
+        // Assert
+        assert!(torn.is_err());
+        assert!(unsynced.is_err());
+        assert_eq!((good.clone(), good.len() as u64), after_failures);
+        let bytes = wal.file.bytes.borrow();
+        assert_eq!(bincode::serialize(&remove)?, bytes[good.len() + 8..]);
+        assert_eq!(bytes.len() as u64, wal.len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_fails_for_good_when_torn_record_stays() -> Result<()> {
+        // Arrange
+        let mut wal = Wal::new(FailingFile::default(), SyncPolicy::Never)?;
+        wal.file.budget.set(3);
+        wal.file.fail_set_len.set(true);
+        let torn = wal.append(&Record::<_, &i32>::Remove { from: 0, key: &1 });
+
+        // Act
+        wal.file.budget.set(usize::MAX);
+        wal.file.fail_set_len.set(false);
+        let result = wal.append(&Record::<_, &i32>::Remove { from: 0, key: &1 });
+
+        // Assert
+        assert!(torn.is_err());
+        let error = result.err().unwrap().downcast::<GojoError>()?;
+        assert!(matches!(error, GojoError::FailedLog));
+        assert_eq!(3, wal.file.bytes.borrow().len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_appends_after_recovered_records() -> Result<()> {
+        // Arrange
+        let (path, _) = write_log("reopen")?;
+        let mut bytes = fs::read(&path)?;
+        bytes.extend_from_slice(&[30, 0, 0]);
+        fs::write(&path, bytes)?;
+
+        // Act
+        let mut gojo = WalGojo::open(&path, SyncPolicy::Never)?;
+        gojo.insert(11, 110)?;
+        gojo.remove_at(2, &0)?;
+        gojo.sync()?;
+        let expected = every_version(gojo.gojo());
+        drop(gojo);
+
+        // Assert
+        let recovered: Gojo<i32, i32> = Gojo::recover(&path)?;
+        assert_eq!(expected, every_version(&recovered));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
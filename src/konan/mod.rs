@@ -40,7 +40,7 @@ impl<T: Ord> Konan<T> {
         Self::default()
     }
 
-    pub fn iter(&self) -> KonanIter<T> {
+    pub fn iter(&self) -> KonanIter<'_, T> {
         KonanIter {
             curr_index: 0,
             konan: self,
@@ -385,7 +385,7 @@ impl<T: Ord> Konan<T> {
 
     #[inline]
     fn is_node_right_child(&self, leaf: &Leaf) -> bool {
-        (leaf.start / (leaf.end - leaf.start + 1)) % 2 != 0
+        !(leaf.start / (leaf.end - leaf.start + 1)).is_multiple_of(2)
     }
 
     pub fn insert(&mut self, v: T) {
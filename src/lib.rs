@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 mod furamengo;
 pub mod gojo;
 pub mod konan;
@@ -40,7 +40,9 @@ fn random_only_insert() -> Result<()> {
 
     for stm in stms {
         match stm {
-            gojo::parser::Statement::Insert(value) => gojo.insert(value, value),
+            gojo::parser::Statement::Insert(value) => {
+                gojo.insert(value, value);
+            }
             _ => bail!("Should not come here"),
         }
     }
@@ -131,3 +133,62 @@ fn successor() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(feature = "serde")]
+#[test]
+fn write_and_read_every_version() -> Result<()> {
+    for input in [
+        include_str!("./inputs/01.txt"),
+        include_str!("./inputs/02.txt"),
+    ] {
+        // Arrange
+        let p = ParserVagaba::default();
+        let mut gojo: Gojo<i32, i32> = Gojo::default();
+        for stm in p.parse_lines(input)? {
+            match stm {
+                gojo::parser::Statement::Insert(value) => {
+                    gojo.insert(value, value);
+                }
+                gojo::parser::Statement::Remove(value) => {
+                    gojo.college_remove(&value);
+                }
+                _ => bail!("Should not come here"),
+            }
+        }
+        let mut buffer = Vec::new();
+
+        // Act
+        gojo.write_to(&mut buffer)?;
+        let mut read: Gojo<i32, i32> = Gojo::read_from(buffer.as_slice())?;
+
+        // Assert
+        assert_eq!(gojo.latest_version(), read.latest_version());
+        for version in 0..=gojo.latest_version() {
+            let expected: Vec<_> = gojo
+                .node_info_iter(version)?
+                .map(|info| (info.key, info.value, info.color, info.depth))
+                .collect();
+            let actual: Vec<_> = read
+                .node_info_iter(version)?
+                .map(|info| (info.key, info.value, info.color, info.depth))
+                .collect();
+            assert_eq!(expected, actual, "for version {}", version);
+            for key in 0..25 {
+                assert_eq!(
+                    gojo.successor_by_key(&key, version),
+                    read.successor_by_key(&key, version),
+                    "for key {} in version {}",
+                    key,
+                    version
+                );
+            }
+        }
+
+        read.insert(4242, 4242);
+        let latest = read.latest_version();
+        assert_eq!(Some(&4242), read.get(&4242, latest));
+        assert_eq!(None, gojo.get(&4242, gojo.latest_version()));
+    }
+
+    Ok(())
+}